@@ -0,0 +1,329 @@
+use crate::wav::{FmtSubChunk, WavError};
+
+const IMA_INDEX_TABLE: [i8; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const IMA_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97,
+    107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428,
+    4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350,
+    22385, 24623, 27086, 29794, 32767
+];
+
+const MS_ADAPTATION_TABLE: [i32; 16] = [230, 230, 230, 230, 307, 409, 512, 614, 768, 614, 512, 409, 307, 230, 230, 230];
+
+const MS_MAX_CHANNELS: usize = 8;
+
+const MS_DEFAULT_COEFFICIENTS: [(i32, i32); 7] = [(256, 0), (512, -256), (0, 0), (192, 64), (240, 0), (460, -208), (392, -232)];
+
+#[derive(Debug, Clone)]
+pub struct ImaAdpcm {
+    pub channels: usize,
+    pub block_align: usize,
+    pub samples_per_block: usize
+}
+
+impl ImaAdpcm {
+    pub fn from_fmt(fmt: &FmtSubChunk) -> Result<Self, WavError> {
+        let channels = fmt.channels as usize;
+        let block_align = fmt.block_align as usize;
+
+        if fmt.bits_per_sample != 4 {
+            return Err(WavError::UnsupportedBitDepth(fmt.bits_per_sample));
+        }
+
+        if channels == 0 || block_align < 4 * channels {
+            return Err(WavError::Truncated);
+        }
+
+        Ok(ImaAdpcm {
+            channels,
+            block_align,
+            samples_per_block: frames_in_ima_block(block_align, channels)
+        })
+    }
+
+    pub fn frames_in_block(&self, block_size: usize) -> usize {
+        frames_in_ima_block(block_size, self.channels).min(self.samples_per_block)
+    }
+
+    // each channel starts with a 4 byte header (initial sample, step index, reserved byte) followed by
+    // interleaved groups of 4 bytes per channel holding 8 nibbles, low nibble first
+    pub fn decode_block(&self, block: &[u8], output: &mut Vec<f32>) {
+        let channels = self.channels;
+        output.clear();
+
+        if block.len() < 4 * channels {
+            return;
+        }
+
+        let frames = self.frames_in_block(block.len());
+        output.resize(frames * channels, 0.0);
+
+        for channel in 0..channels {
+            let header = &block[channel * 4..channel * 4 + 4];
+            let mut predictor = i16::from_le_bytes([header[0], header[1]]) as i32;
+            let mut step_index = (header[2] as i32).clamp(0, 88);
+
+            output[channel] = predictor as f32 / 32768.0;
+
+            let mut frame = 1;
+            let mut group_offset = 4 * channels + channel * 4;
+
+            // a trailing block may end partway through a group
+            while frame < frames && group_offset < block.len() {
+                for byte in &block[group_offset..(group_offset + 4).min(block.len())] {
+                    for nibble in [byte & 0x0F, byte >> 4] {
+                        if frame >= frames {
+                            break;
+                        }
+
+                        let step = IMA_STEP_TABLE[step_index as usize];
+                        let mut difference = step >> 3;
+                        if nibble & 1 != 0 {
+                            difference += step >> 2;
+                        }
+                        if nibble & 2 != 0 {
+                            difference += step >> 1;
+                        }
+                        if nibble & 4 != 0 {
+                            difference += step;
+                        }
+                        if nibble & 8 != 0 {
+                            difference = -difference;
+                        }
+
+                        predictor = (predictor + difference).clamp(i16::MIN as i32, i16::MAX as i32);
+                        step_index = (step_index + IMA_INDEX_TABLE[nibble as usize] as i32).clamp(0, 88);
+
+                        output[frame * channels + channel] = predictor as f32 / 32768.0;
+                        frame += 1;
+                    }
+                }
+
+                group_offset += 4 * channels;
+            }
+        }
+    }
+}
+
+fn frames_in_ima_block(block_size: usize, channels: usize) -> usize {
+    if block_size < 4 * channels {
+        return 0;
+    }
+
+    (block_size - 4 * channels) * 2 / channels + 1
+}
+
+#[derive(Debug, Clone)]
+pub struct MsAdpcm {
+    pub channels: usize,
+    pub block_align: usize,
+    pub samples_per_block: usize,
+    pub coefficients: Vec<(i32, i32)>
+}
+
+impl MsAdpcm {
+    pub fn from_fmt(fmt: &FmtSubChunk) -> Result<Self, WavError> {
+        let channels = fmt.channels as usize;
+        let block_align = fmt.block_align as usize;
+
+        if fmt.bits_per_sample != 4 {
+            return Err(WavError::UnsupportedBitDepth(fmt.bits_per_sample));
+        }
+
+        if channels > MS_MAX_CHANNELS {
+            return Err(WavError::UnsupportedChannelCount(fmt.channels));
+        }
+
+        if channels == 0 || block_align < 7 * channels {
+            return Err(WavError::Truncated);
+        }
+
+        // the fmt extension holds wSamplesPerBlock, wNumCoef and the coefficient pairs
+        let extra = &fmt.extra;
+        let coefficients = if extra.len() >= 4 {
+            let count = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+            if extra.len() < 4 + count * 4 {
+                return Err(WavError::Truncated);
+            }
+
+            (0..count)
+                .map(|index| {
+                    let at = 4 + index * 4;
+                    let coefficient_1 = i16::from_le_bytes([extra[at], extra[at + 1]]) as i32;
+                    let coefficient_2 = i16::from_le_bytes([extra[at + 2], extra[at + 3]]) as i32;
+                    (coefficient_1, coefficient_2)
+                })
+                .collect()
+        } else {
+            MS_DEFAULT_COEFFICIENTS.to_vec()
+        };
+
+        Ok(MsAdpcm {
+            channels,
+            block_align,
+            samples_per_block: frames_in_ms_block(block_align, channels),
+            coefficients
+        })
+    }
+
+    pub fn frames_in_block(&self, block_size: usize) -> usize {
+        frames_in_ms_block(block_size, self.channels).min(self.samples_per_block)
+    }
+
+    // the block header holds per channel predictor indexes, initial deltas and the first two samples,
+    // followed by nibbles interleaved across channels, high nibble first
+    pub fn decode_block(&self, block: &[u8], output: &mut Vec<f32>) {
+        let channels = self.channels;
+        output.clear();
+
+        if block.len() < 7 * channels {
+            return;
+        }
+
+        let frames = self.frames_in_block(block.len());
+        output.resize(frames * channels, 0.0);
+
+        let mut coefficients = [(0i32, 0i32); MS_MAX_CHANNELS];
+        let mut deltas = [0i32; MS_MAX_CHANNELS];
+        let mut sample_1 = [0i32; MS_MAX_CHANNELS];
+        let mut sample_2 = [0i32; MS_MAX_CHANNELS];
+
+        let read_i16 = |at: usize| i16::from_le_bytes([block[at], block[at + 1]]) as i32;
+
+        for channel in 0..channels {
+            let predictor = block[channel] as usize;
+            coefficients[channel] = *self.coefficients.get(predictor).unwrap_or(&MS_DEFAULT_COEFFICIENTS[0]);
+            deltas[channel] = read_i16(channels + channel * 2);
+            sample_1[channel] = read_i16(3 * channels + channel * 2);
+            sample_2[channel] = read_i16(5 * channels + channel * 2);
+
+            // the older sample is played first
+            output[channel] = sample_2[channel] as f32 / 32768.0;
+            if frames > 1 {
+                output[channels + channel] = sample_1[channel] as f32 / 32768.0;
+            }
+        }
+
+        let nibbles = block[7 * channels..]
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0x0F]);
+
+        for (index, nibble) in nibbles.enumerate() {
+            let sample_index = 2 * channels + index;
+            if sample_index >= frames * channels {
+                break;
+            }
+
+            let channel = index % channels;
+            let signed_nibble = if nibble & 0x08 != 0 { nibble as i32 - 16 } else { nibble as i32 };
+            let (coefficient_1, coefficient_2) = coefficients[channel];
+
+            let predictor = (sample_1[channel] * coefficient_1 + sample_2[channel] * coefficient_2) >> 8;
+            let sample = (predictor + signed_nibble * deltas[channel]).clamp(i16::MIN as i32, i16::MAX as i32);
+
+            sample_2[channel] = sample_1[channel];
+            sample_1[channel] = sample;
+            deltas[channel] = ((MS_ADAPTATION_TABLE[nibble as usize] * deltas[channel]) >> 8).max(16);
+
+            output[sample_index] = sample as f32 / 32768.0;
+        }
+    }
+}
+
+fn frames_in_ms_block(block_size: usize, channels: usize) -> usize {
+    if block_size < 7 * channels {
+        return 0;
+    }
+
+    (block_size - 7 * channels) * 2 / channels + 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImaAdpcm, MsAdpcm};
+    use crate::wav::{FmtSubChunk, WavError, WAVE_FORMAT_ADPCM, WAVE_FORMAT_IMA_ADPCM};
+
+    fn fmt(format_code: u16, channels: u16, block_align: u16, bits_per_sample: u16, extra: Vec<u8>) -> FmtSubChunk {
+        FmtSubChunk {
+            chunk_id: String::from("fmt "),
+            chunk_size: 20 + extra.len() as u64,
+            audio_format: format_code,
+            channels,
+            sample_rate: 22050,
+            byte_rate: 11025,
+            block_align,
+            bits_per_sample,
+            extensible: None,
+            extra
+        }
+    }
+
+    fn samples(values: &[i32]) -> Vec<f32> {
+        values.iter().map(|value| *value as f32 / 32768.0).collect()
+    }
+
+    #[test]
+    fn decodes_ima_block() {
+        let ima = ImaAdpcm::from_fmt(&fmt(WAVE_FORMAT_IMA_ADPCM, 1, 8, 4, Vec::new())).unwrap();
+        assert_eq!(ima.samples_per_block, 9);
+
+        // predictor 0 at step index 0, then a 7 nibble jumps the step index up by 8 and zeros walk it back down
+        let mut output = Vec::new();
+        ima.decode_block(&[0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00], &mut output);
+        assert_eq!(output, samples(&[0, 11, 13, 14, 15, 16, 17, 18, 19]));
+
+        // a trailing block shorter than block_align still decodes what it holds
+        ima.decode_block(&[0x00, 0x00, 0x00, 0x00, 0x07], &mut output);
+        assert_eq!(output, samples(&[0, 11, 13]));
+    }
+
+    #[test]
+    fn decodes_ms_block() {
+        let ms = MsAdpcm::from_fmt(&fmt(WAVE_FORMAT_ADPCM, 1, 8, 4, Vec::new())).unwrap();
+        assert_eq!(ms.samples_per_block, 4);
+
+        // predictor 0, delta 16, sample 1 is 100 and sample 2 is 50, then nibbles +1 and -1
+        let mut output = Vec::new();
+        ms.decode_block(&[0x00, 16, 0, 100, 0, 50, 0, 0x1F], &mut output);
+        assert_eq!(output, samples(&[50, 100, 116, 100]));
+    }
+
+    #[test]
+    fn reads_ms_coefficients() {
+        let mut extra = Vec::new();
+        extra.extend(4u16.to_le_bytes());
+        extra.extend(1u16.to_le_bytes());
+        extra.extend(512i16.to_le_bytes());
+        extra.extend((-256i16).to_le_bytes());
+
+        let ms = MsAdpcm::from_fmt(&fmt(WAVE_FORMAT_ADPCM, 1, 8, 4, extra)).unwrap();
+        assert_eq!(ms.coefficients, [(512, -256)]);
+    }
+
+    #[test]
+    fn rejects_bad_fmt() {
+        assert!(matches!(ImaAdpcm::from_fmt(&fmt(WAVE_FORMAT_IMA_ADPCM, 1, 8, 3, Vec::new())), Err(WavError::UnsupportedBitDepth(3))));
+        assert!(matches!(ImaAdpcm::from_fmt(&fmt(WAVE_FORMAT_IMA_ADPCM, 2, 6, 4, Vec::new())), Err(WavError::Truncated)));
+        assert!(matches!(MsAdpcm::from_fmt(&fmt(WAVE_FORMAT_ADPCM, 9, 256, 4, Vec::new())), Err(WavError::UnsupportedChannelCount(9))));
+
+        // a coefficient count past the end of the extension
+        let extra = [4u8, 0, 7, 0].to_vec();
+        assert!(matches!(MsAdpcm::from_fmt(&fmt(WAVE_FORMAT_ADPCM, 1, 8, 4, extra)), Err(WavError::Truncated)));
+    }
+
+    #[test]
+    fn short_blocks_decode_to_nothing() {
+        let ima = ImaAdpcm::from_fmt(&fmt(WAVE_FORMAT_IMA_ADPCM, 2, 16, 4, Vec::new())).unwrap();
+        let ms = MsAdpcm::from_fmt(&fmt(WAVE_FORMAT_ADPCM, 2, 16, 4, Vec::new())).unwrap();
+
+        let mut output = vec![1.0];
+        ima.decode_block(&[0x00; 7], &mut output);
+        assert!(output.is_empty());
+
+        output.push(1.0);
+        ms.decode_block(&[0x00; 13], &mut output);
+        assert!(output.is_empty());
+    }
+}
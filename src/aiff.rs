@@ -0,0 +1,688 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::codec::Codec;
+use crate::decoder::{Decoder, DecoderError, StreamInfo};
+use crate::id3::read_id3v2;
+use crate::markers::{Marker, SampleLoop};
+use crate::metadata::Metadata;
+use crate::sample_format::SampleFormat;
+use crate::wav::{default_channel_mask, Endianness};
+
+// AIFF is big-endian throughout, only the sowt flavour of AIFF-C stores little-endian samples
+const BE: Endianness = Endianness::Big;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormType {
+    Aiff,
+    Aifc
+}
+
+#[allow(dead_code)]
+pub struct Aiff {
+    pub header: AiffHeader,
+    pub codec: Codec,
+    pub channel_mask: u32,
+    pub metadata: Metadata,
+    pub markers: Vec<Marker>,
+    pub loops: Vec<SampleLoop>
+}
+
+impl Aiff {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, AiffError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let header = AiffHeader::from_reader(&mut reader)?;
+
+        if header.ssnd.offset + header.ssnd.size > file_size {
+            return Err(AiffError::Truncated);
+        }
+
+        let (sample_format, endianness) = header.comm.sample_format()?;
+        let codec = Codec::Pcm {
+            sample_format,
+            channels: header.comm.channels as usize,
+            endianness
+        };
+
+        // the AIFF-C speaker layouts only agree with the WAVE ones up to four channels
+        let channel_mask = match header.comm.channels {
+            1..=4 => default_channel_mask(header.comm.channels),
+            _ => 0
+        };
+
+        // ID3 tags tend to be more complete than the text chunks, those only fill in what ID3 lacks
+        let metadata = header.id3.clone().unwrap_or_default().or(header.text.clone());
+
+        let mut markers = header.markers.clone();
+        markers.sort_by_key(|marker| marker.position);
+
+        let loops = header.instrument
+            .as_ref()
+            .and_then(|instrument| instrument.sustain_loop(&header.markers))
+            .into_iter()
+            .collect();
+
+        Ok(Aiff {
+            header,
+            codec,
+            channel_mask,
+            metadata,
+            markers,
+            loops
+        })
+    }
+}
+
+// Decoder backend for AIFF and uncompressed AIFF-C files
+pub struct AiffDecoder {
+    aiff: Aiff,
+    info: StreamInfo,
+    file: File,
+    bytes: Vec<u8>,
+    decoded: Vec<f32>,
+    next_frame: u64
+}
+
+impl Decoder for AiffDecoder {
+    fn open(path: &Path) -> Result<Self, DecoderError> {
+        let aiff = Aiff::new(path)?;
+
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(aiff.header.ssnd.offset))?;
+
+        // COMM holds the frame count, but don't trust it past the end of the sound data
+        let frames = (aiff.header.comm.frames as u64).min(aiff.codec.frame_count(aiff.header.ssnd.size));
+
+        let info = StreamInfo {
+            sample_rate: aiff.header.comm.sample_rate,
+            channels: aiff.header.comm.channels,
+            channel_mask: aiff.channel_mask,
+            frames,
+            markers: aiff.markers.clone(),
+            loops: aiff.loops.clone(),
+            time_reference: None,
+            timecode_rate: None,
+            details: details(&aiff.header)
+        };
+
+        Ok(AiffDecoder {
+            aiff,
+            info,
+            file,
+            bytes: Vec::new(),
+            decoded: Vec::new(),
+            next_frame: 0
+        })
+    }
+
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.aiff.metadata
+    }
+
+    fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError> {
+        let frames = max_frames.min(self.info.frames.saturating_sub(self.next_frame) as usize);
+        if frames == 0 {
+            return Ok(0);
+        }
+
+        self.bytes.resize(frames * self.aiff.codec.block_align(), 0);
+        self.file.read_exact(&mut self.bytes)?;
+
+        self.aiff.codec.decode_block(&self.bytes, &mut self.decoded);
+        samples.extend_from_slice(&self.decoded);
+        self.next_frame += frames as u64;
+
+        Ok(frames)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), DecoderError> {
+        let frame = frame.min(self.info.frames);
+
+        self.file.seek(SeekFrom::Start(self.aiff.header.ssnd.offset + frame * self.aiff.codec.block_align() as u64))?;
+        self.next_frame = frame;
+
+        Ok(())
+    }
+}
+
+// COMM and copyright lines for the track info view
+fn details(header: &AiffHeader) -> Vec<(String, String)> {
+    let comm = &header.comm;
+
+    let format = match header.form_type {
+        FormType::Aiff => format!("AIFF, {} bit", comm.bits_per_sample),
+        FormType::Aifc if comm.compression_name.is_empty() => format!("AIFF-C {}, {} bit", comm.compression_type, comm.bits_per_sample),
+        FormType::Aifc => format!("AIFF-C {} ({}), {} bit", comm.compression_type, comm.compression_name, comm.bits_per_sample)
+    };
+
+    let mut lines = vec![(String::from("Format"), format)];
+
+    if let Some(copyright) = &header.copyright {
+        lines.push((String::from("Copyright"), copyright.clone()));
+    }
+
+    lines
+}
+
+#[derive(Debug)]
+pub enum AiffError {
+    Io(std::io::Error),
+    InvalidMagic,
+    MissingCommChunk,
+    MissingSoundChunk,
+    UnsupportedCompression(String),
+    UnsupportedBitDepth(u16),
+    UnsupportedChannelCount(u16),
+    InvalidSampleRate,
+    Truncated
+}
+
+impl Display for AiffError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiffError::Io(err) => write!(f, "I/O error: {}", err),
+            AiffError::InvalidMagic => write!(f, "not an AIFF file"),
+            AiffError::MissingCommChunk => write!(f, "no COMM chunk found"),
+            AiffError::MissingSoundChunk => write!(f, "no SSND chunk found"),
+            AiffError::UnsupportedCompression(compression) => write!(f, "unsupported AIFF-C compression {:?}", compression),
+            AiffError::UnsupportedBitDepth(bits) => write!(f, "unsupported bit depth {}", bits),
+            AiffError::UnsupportedChannelCount(channels) => write!(f, "unsupported channel count {}", channels),
+            AiffError::InvalidSampleRate => write!(f, "invalid sample rate"),
+            AiffError::Truncated => write!(f, "file is truncated")
+        }
+    }
+}
+
+impl Error for AiffError {}
+
+impl From<std::io::Error> for AiffError {
+    fn from(err: std::io::Error) -> Self {
+        if err.kind() == ErrorKind::UnexpectedEof {
+            AiffError::Truncated
+        } else {
+            AiffError::Io(err)
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub struct AiffHeader {
+    pub form_type: FormType,
+    pub comm: CommChunk,
+    pub ssnd: SoundChunk,
+    // NAME, AUTH and ANNO chunks
+    pub text: Metadata,
+    pub copyright: Option<String>,
+    pub id3: Option<Metadata>,
+    pub markers: Vec<Marker>,
+    pub instrument: Option<InstrumentChunk>
+}
+
+impl AiffHeader {
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self, AiffError> {
+        // chunk sizes come from the file, bodies are only read once they're known to fit in it
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut form_header = [0u8; 12];
+        reader.read_exact(&mut form_header)?;
+
+        if &form_header[0..4] != b"FORM" {
+            return Err(AiffError::InvalidMagic);
+        }
+        let form_type = match &form_header[8..12] {
+            b"AIFF" => FormType::Aiff,
+            b"AIFC" => FormType::Aifc,
+            _ => return Err(AiffError::InvalidMagic)
+        };
+
+        let form_end = BE.u32(&form_header, 4) as u64 + 8;
+        let mut offset: u64 = 12;
+
+        let mut comm: Option<CommChunk> = None;
+        let mut ssnd: Option<SoundChunk> = None;
+        let mut text = Metadata::default();
+        let mut copyright: Option<String> = None;
+        let mut id3: Option<Metadata> = None;
+        let mut markers: Vec<Marker> = Vec::new();
+        let mut instrument: Option<InstrumentChunk> = None;
+
+        while offset + 8 <= form_end {
+            let mut chunk_header = [0u8; 8];
+            if reader.read_exact(&mut chunk_header).is_err() {
+                break;
+            }
+            let id: [u8; 4] = chunk_header[0..4].try_into().unwrap();
+            let size = BE.u32(&chunk_header, 4) as u64;
+            let body_offset = offset + 8;
+            let available = stream_end.saturating_sub(body_offset);
+
+            match &id {
+                b"COMM" => {
+                    let comm_bytes = read_chunk_body(reader, size, available)?;
+                    comm = Some(CommChunk::from_chunk_bytes(&comm_bytes, form_type)?);
+                }
+                b"SSND" => {
+                    // the sound data itself is left in place, only its leading offset and block size are read
+                    let mut ssnd_header = [0u8; 8];
+                    reader.read_exact(&mut ssnd_header)?;
+                    let data_offset = BE.u32(&ssnd_header, 0) as u64;
+
+                    ssnd = Some(SoundChunk {
+                        offset: body_offset + 8 + data_offset,
+                        size: size.saturating_sub(8 + data_offset),
+                        block_size: BE.u32(&ssnd_header, 4)
+                    });
+                }
+                b"NAME" => text.title = read_text(&read_chunk_body(reader, size, available)?),
+                b"AUTH" => text.artist = read_text(&read_chunk_body(reader, size, available)?),
+                // there may be several annotations, the first one is kept
+                b"ANNO" => {
+                    let annotation = read_text(&read_chunk_body(reader, size, available)?);
+                    text.comment = text.comment.or(annotation);
+                }
+                b"(c) " => copyright = read_text(&read_chunk_body(reader, size, available)?),
+                b"ID3 " | b"id3 " => id3 = read_id3v2(&read_chunk_body(reader, size, available)?),
+                b"MARK" => markers = read_markers(&read_chunk_body(reader, size, available)?),
+                b"INST" => instrument = InstrumentChunk::from_chunk_bytes(&read_chunk_body(reader, size, available)?),
+                _ => {}
+            }
+
+            // chunks are word aligned, odd sized chunks are followed by a pad byte
+            offset = body_offset + size + (size & 1);
+            reader.seek(SeekFrom::Start(offset))?;
+        }
+
+        let comm = comm.ok_or(AiffError::MissingCommChunk)?;
+        let ssnd = ssnd.ok_or(AiffError::MissingSoundChunk)?;
+
+        Ok(AiffHeader {
+            form_type,
+            comm,
+            ssnd,
+            text,
+            copyright,
+            id3,
+            markers,
+            instrument
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CommChunk {
+    pub channels: u16,
+    pub frames: u32,
+    pub bits_per_sample: u16,
+    pub sample_rate: u32,
+    // AIFF-C only, plain AIFF is always uncompressed big-endian PCM
+    pub compression_type: String,
+    pub compression_name: String
+}
+
+impl CommChunk {
+    pub fn from_chunk_bytes(comm_bytes: &[u8], form_type: FormType) -> Result<Self, AiffError> {
+        if comm_bytes.len() < 18 {
+            return Err(AiffError::Truncated);
+        }
+
+        let channels = BE.u16(comm_bytes, 0);
+        if channels == 0 {
+            return Err(AiffError::UnsupportedChannelCount(channels));
+        }
+
+        let sample_rate = extended_to_f64(&comm_bytes[8..18]).round();
+        if !(1.0..=u32::MAX as f64).contains(&sample_rate) {
+            return Err(AiffError::InvalidSampleRate);
+        }
+
+        let (compression_type, compression_name) = match form_type {
+            FormType::Aiff => (String::from("NONE"), String::new()),
+            FormType::Aifc => {
+                let compression_type = comm_bytes.get(18..22).ok_or(AiffError::Truncated)?;
+                let compression_name = comm_bytes.get(22..).and_then(pascal_string).unwrap_or_default();
+                (String::from_utf8_lossy(compression_type).to_string(), compression_name)
+            }
+        };
+
+        Ok(CommChunk {
+            channels,
+            frames: BE.u32(comm_bytes, 2),
+            bits_per_sample: BE.u16(comm_bytes, 6),
+            sample_rate: sample_rate as u32,
+            compression_type,
+            compression_name
+        })
+    }
+
+    // how the sound data is stored, integer samples are left aligned in whole bytes
+    pub fn sample_format(&self) -> Result<(SampleFormat, Endianness), AiffError> {
+        let integer_format = |bits: u16| match bits {
+            1..=8 => Ok(SampleFormat::I8),
+            9..=16 => Ok(SampleFormat::I16),
+            17..=24 => Ok(SampleFormat::I24),
+            25..=32 => Ok(SampleFormat::I32),
+            _ => Err(AiffError::UnsupportedBitDepth(bits))
+        };
+
+        match self.compression_type.as_str() {
+            "NONE" | "twos" => Ok((integer_format(self.bits_per_sample)?, Endianness::Big)),
+            "sowt" => Ok((integer_format(self.bits_per_sample)?, Endianness::Little)),
+            "raw " => Ok((SampleFormat::U8, Endianness::Big)),
+            "in24" => Ok((SampleFormat::I24, Endianness::Big)),
+            "in32" => Ok((SampleFormat::I32, Endianness::Big)),
+            "fl32" | "FL32" => Ok((SampleFormat::F32, Endianness::Big)),
+            "fl64" | "FL64" => Ok((SampleFormat::F64, Endianness::Big)),
+            "alaw" | "ALAW" => Ok((SampleFormat::ALaw, Endianness::Big)),
+            "ulaw" | "ULAW" => Ok((SampleFormat::MuLaw, Endianness::Big)),
+            other => Err(AiffError::UnsupportedCompression(other.to_string()))
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SoundChunk {
+    // absolute offset of the first sample frame and the size of the sound data from there
+    pub offset: u64,
+    pub size: u64,
+    pub block_size: u32
+}
+
+#[derive(Debug)]
+pub struct InstrumentChunk {
+    pub sustain_mode: u16,
+    pub sustain_start: u32,
+    pub sustain_end: u32
+}
+
+impl InstrumentChunk {
+    pub fn from_chunk_bytes(inst_bytes: &[u8]) -> Option<Self> {
+        if inst_bytes.len() < 20 {
+            return None;
+        }
+
+        Some(InstrumentChunk {
+            sustain_mode: BE.u16(inst_bytes, 8),
+            sustain_start: BE.u16(inst_bytes, 10) as u32,
+            sustain_end: BE.u16(inst_bytes, 12) as u32
+        })
+    }
+
+    // the sustain loop refers to MARK markers, which sit between frames, so the end marker follows the last looped frame
+    pub fn sustain_loop(&self, markers: &[Marker]) -> Option<SampleLoop> {
+        if self.sustain_mode == 0 {
+            return None;
+        }
+
+        let position = |id: u32| markers.iter().find(|marker| marker.id == id).map(|marker| marker.position);
+        let start = position(self.sustain_start)?;
+        let end = position(self.sustain_end)?;
+
+        (start < end).then_some(SampleLoop {
+            start,
+            end: end - 1,
+            play_count: 0
+        })
+    }
+}
+
+// the markers of a MARK chunk, positions are in sample frames from the start of the sound data
+fn read_markers(mark_bytes: &[u8]) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    if mark_bytes.len() < 2 {
+        return markers;
+    }
+
+    let count = BE.u16(mark_bytes, 0);
+    let mut offset = 2;
+
+    for _ in 0..count {
+        if offset + 7 > mark_bytes.len() {
+            break;
+        }
+
+        let name_length = mark_bytes[offset + 6] as usize;
+        markers.push(Marker {
+            id: BE.u16(mark_bytes, offset) as u32,
+            position: BE.u32(mark_bytes, offset + 2) as u64,
+            name: pascal_string(&mark_bytes[offset + 6..]).filter(|name| !name.is_empty())
+        });
+
+        // the name's count byte and text are padded to an even length
+        offset += 6 + 1 + name_length + ((name_length + 1) & 1);
+    }
+
+    markers
+}
+
+// a count byte followed by that many characters
+fn pascal_string(bytes: &[u8]) -> Option<String> {
+    let length = *bytes.first()? as usize;
+    let text = bytes.get(1..1 + length)?;
+
+    Some(String::from_utf8_lossy(text).trim().to_string())
+}
+
+fn read_text(text_bytes: &[u8]) -> Option<String> {
+    let text_end = text_bytes.iter().position(|byte| *byte == 0).unwrap_or(text_bytes.len());
+    let text = String::from_utf8_lossy(&text_bytes[..text_end]).trim().to_string();
+
+    Some(text).filter(|text| !text.is_empty())
+}
+
+// the sample rate is an 80-bit IEEE 754 extended float, with an explicit integer bit in the mantissa
+fn extended_to_f64(bytes: &[u8]) -> f64 {
+    let sign_exponent = BE.u16(bytes, 0);
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+
+    let exponent = (sign_exponent & 0x7FFF) as i32 - 16383 - 63;
+    let value = mantissa as f64 * 2f64.powi(exponent);
+
+    if sign_exponent & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+fn read_chunk_body<R: Read>(reader: &mut R, size: u64, available: u64) -> Result<Vec<u8>, AiffError> {
+    if size > available {
+        return Err(AiffError::Truncated);
+    }
+
+    let mut bytes = vec![0u8; size as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{extended_to_f64, AiffDecoder, AiffError, AiffHeader, CommChunk, FormType};
+    use crate::decoder::Decoder;
+    use crate::sample_format::SampleFormat;
+    use crate::wav::Endianness;
+
+    fn extended(value: u64) -> Vec<u8> {
+        let shift = value.leading_zeros();
+        let mut bytes = ((16383 + 63 - shift) as u16).to_be_bytes().to_vec();
+        bytes.extend((value << shift).to_be_bytes());
+        bytes
+    }
+
+    // AIFF-C adds the compression type and a pascal string name
+    fn comm(channels: u16, frames: u32, bits: u16, sample_rate: u64, compression: Option<(&[u8; 4], &[u8])>) -> Vec<u8> {
+        let mut bytes = channels.to_be_bytes().to_vec();
+        bytes.extend(frames.to_be_bytes());
+        bytes.extend(bits.to_be_bytes());
+        bytes.extend(if sample_rate == 0 { vec![0; 10] } else { extended(sample_rate) });
+        if let Some((compression_type, name)) = compression {
+            bytes.extend(compression_type);
+            bytes.push(name.len() as u8);
+            bytes.extend(name);
+        }
+        bytes
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((body.len() as u32).to_be_bytes());
+        bytes.extend(body);
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn ssnd(data_offset: u32, samples: &[u8]) -> Vec<u8> {
+        let mut body = data_offset.to_be_bytes().to_vec();
+        body.extend(0u32.to_be_bytes());
+        body.extend(vec![0; data_offset as usize]);
+        body.extend(samples);
+        chunk(b"SSND", &body)
+    }
+
+    fn form(form_type: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut bytes = b"FORM".to_vec();
+        bytes.extend((body.len() as u32 + 4).to_be_bytes());
+        bytes.extend(form_type);
+        bytes.extend(body);
+        bytes
+    }
+
+    fn read_header(bytes: Vec<u8>) -> Result<AiffHeader, AiffError> {
+        AiffHeader::from_reader(&mut Cursor::new(bytes))
+    }
+
+    #[test]
+    fn converts_extended_floats() {
+        assert_eq!(extended_to_f64(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]), 44100.0);
+        assert_eq!(extended_to_f64(&[0x40, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]), 48000.0);
+        assert_eq!(extended_to_f64(&[0xC0, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]), -48000.0);
+        // 22050.5 needs a fraction bit
+        assert_eq!(extended_to_f64(&[0x40, 0x0D, 0xAC, 0x45, 0, 0, 0, 0, 0, 0]), 22050.5);
+        assert_eq!(extended_to_f64(&extended(2822400)), 2822400.0);
+        assert_eq!(extended_to_f64(&[0; 10]), 0.0);
+    }
+
+    #[test]
+    fn reads_aiff_header() {
+        let mut mark = 3u16.to_be_bytes().to_vec();
+        for (id, position, name) in [(1u16, 1000u32, &b"Start"[..]), (2, 3000, b"End"), (3, 500, b"")] {
+            mark.extend(id.to_be_bytes());
+            mark.extend(position.to_be_bytes());
+            mark.push(name.len() as u8);
+            mark.extend(name);
+            if name.len() % 2 == 0 {
+                mark.push(0);
+            }
+        }
+        // base note, detune, note and velocity ranges and gain, then a forward sustain loop from marker 1 to 2
+        let mut inst = vec![60, 0, 0, 127, 0, 127, 0, 0];
+        inst.extend([0, 1, 0, 1, 0, 2, 0, 0, 0, 0, 0, 0]);
+
+        let header = read_header(form(b"AIFF", &[
+            chunk(b"COMM", &comm(2, 4000, 16, 44100, None)),
+            chunk(b"NAME", b"Song"),
+            chunk(b"ANNO", b"First"),
+            chunk(b"ANNO", b"Second"),
+            chunk(b"(c) ", b"2024 Someone"),
+            chunk(b"MARK", &mark),
+            chunk(b"INST", &inst),
+            ssnd(4, &[0; 16])
+        ])).unwrap();
+
+        let comm = &header.comm;
+        assert_eq!(header.form_type, FormType::Aiff);
+        assert_eq!((comm.channels, comm.frames, comm.bits_per_sample, comm.sample_rate), (2, 4000, 16, 44100));
+        assert_eq!(comm.sample_format().unwrap(), (SampleFormat::I16, Endianness::Big));
+        assert_eq!((header.ssnd.size, header.ssnd.block_size), (16, 0));
+        assert_eq!(header.text.title.as_deref(), Some("Song"));
+        assert_eq!(header.text.comment.as_deref(), Some("First"));
+        assert_eq!(header.copyright.as_deref(), Some("2024 Someone"));
+
+        let markers: Vec<_> = header.markers.iter().map(|marker| (marker.id, marker.position, marker.name.as_deref())).collect();
+        assert_eq!(markers, [(1, 1000, Some("Start")), (2, 3000, Some("End")), (3, 500, None)]);
+        let sample_loop = header.instrument.unwrap().sustain_loop(&header.markers).unwrap();
+        assert_eq!((sample_loop.start, sample_loop.end, sample_loop.play_count), (1000, 2999, 0));
+    }
+
+    #[test]
+    fn reads_aifc_compression_types() {
+        let format = |compression_type: &[u8; 4], bits: u16| {
+            CommChunk::from_chunk_bytes(&comm(1, 0, bits, 8000, Some((compression_type, b"name"))), FormType::Aifc)?.sample_format()
+        };
+
+        assert_eq!(format(b"sowt", 24).unwrap(), (SampleFormat::I24, Endianness::Little));
+        assert_eq!(format(b"twos", 8).unwrap(), (SampleFormat::I8, Endianness::Big));
+        assert_eq!(format(b"raw ", 8).unwrap(), (SampleFormat::U8, Endianness::Big));
+        assert_eq!(format(b"fl64", 64).unwrap(), (SampleFormat::F64, Endianness::Big));
+        assert_eq!(format(b"ulaw", 16).unwrap(), (SampleFormat::MuLaw, Endianness::Big));
+        assert!(matches!(format(b"ima4", 16), Err(AiffError::UnsupportedCompression(name)) if name == "ima4"));
+        assert!(matches!(format(b"NONE", 40), Err(AiffError::UnsupportedBitDepth(40))));
+
+        let comm = CommChunk::from_chunk_bytes(&comm(1, 0, 16, 8000, Some((b"sowt", b"little endian"))), FormType::Aifc).unwrap();
+        assert_eq!((comm.compression_type.as_str(), comm.compression_name.as_str()), ("sowt", "little endian"));
+    }
+
+    #[test]
+    fn decodes_sowt_samples() {
+        let samples: Vec<u8> = [0i16, 16384, -16384, 32767].iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        let bytes = form(b"AIFC", &[
+            chunk(b"FVER", &0xA2805140u32.to_be_bytes()),
+            chunk(b"COMM", &comm(2, 2, 16, 48000, Some((b"sowt", b"")))),
+            // an odd sized chunk is padded
+            chunk(b"AUTH", b"Ban"),
+            ssnd(0, &samples)
+        ]);
+
+        let path = std::env::temp_dir().join(format!("aiff-sowt-{}.aifc", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let result = AiffDecoder::open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut decoder = result.unwrap();
+        assert_eq!((decoder.info().sample_rate, decoder.info().channels, decoder.info().frames), (48000, 2, 2));
+        assert_eq!(decoder.metadata().artist.as_deref(), Some("Ban"));
+
+        let mut decoded = Vec::new();
+        assert_eq!(decoder.read_frames(&mut decoded, 16).unwrap(), 2);
+        assert_eq!(decoded, [0.0, 0.5, -0.5, 32767.0 / 32768.0]);
+        assert_eq!(decoder.read_frames(&mut decoded, 16).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let sound = ssnd(0, &[0; 4]);
+
+        assert!(matches!(read_header(b"RIFF\0\0\0\x04AIFF".to_vec()), Err(AiffError::InvalidMagic)));
+        assert!(matches!(read_header(form(b"WAVE", &[])), Err(AiffError::InvalidMagic)));
+        assert!(matches!(read_header(form(b"AIFF", std::slice::from_ref(&sound))), Err(AiffError::MissingCommChunk)));
+        assert!(matches!(read_header(form(b"AIFF", &[chunk(b"COMM", &comm(1, 2, 16, 8000, None))])), Err(AiffError::MissingSoundChunk)));
+
+        assert!(matches!(read_header(form(b"AIFF", &[chunk(b"COMM", &comm(0, 2, 16, 8000, None)), sound.clone()])), Err(AiffError::UnsupportedChannelCount(0))));
+        assert!(matches!(read_header(form(b"AIFF", &[chunk(b"COMM", &comm(1, 2, 16, 0, None)), sound.clone()])), Err(AiffError::InvalidSampleRate)));
+        assert!(matches!(read_header(form(b"AIFF", &[chunk(b"COMM", &comm(1, 2, 16, 8000, None)[..17]), sound.clone()])), Err(AiffError::Truncated)));
+        // AIFF-C needs the compression type
+        assert!(matches!(read_header(form(b"AIFC", &[chunk(b"COMM", &comm(1, 2, 16, 8000, None)), sound.clone()])), Err(AiffError::Truncated)));
+
+        // a chunk claiming more bytes than the file holds
+        let mut bytes = form(b"AIFF", &[chunk(b"COMM", &comm(1, 2, 16, 8000, None)), sound]);
+        bytes[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(matches!(read_header(bytes), Err(AiffError::Truncated)));
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::{Arc};
 use std::time::Duration;
-use crossbeam_queue::SegQueue;
+use crossbeam_queue::ArrayQueue;
 use crossterm::event::{poll, read};
 use crossterm::event::Event;
 use crate::gui::Gui;
@@ -14,8 +14,9 @@ pub enum AppEvent {
 }
 
 impl App {
-    pub fn new(from_gui_queue: Arc<SegQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<SegQueue<PlayerToGuiCommands>>) {
-        let mut gui = Gui::new(from_gui_queue, to_gui_queue);
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(from_gui_queue: Arc<ArrayQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<ArrayQueue<PlayerToGuiCommands>>, device_sample_rate: u32) {
+        let mut gui = Gui::new(from_gui_queue, to_gui_queue, device_sample_rate);
 
         loop {
             gui.draw();
@@ -0,0 +1,225 @@
+use std::fmt::{Display, Formatter};
+
+const DEFAULT_TIMECODE_RATE: f64 = 25.0;
+
+// Broadcast extension chunk as described in EBU Tech 3285
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct BextChunk {
+    pub description: String,
+    pub originator: String,
+    pub originator_reference: String,
+    pub origination_date: String,
+    pub origination_time: String,
+    pub time_reference: u64,
+    pub version: u16,
+    pub loudness_value: Option<f32>,
+    pub loudness_range: Option<f32>,
+    pub max_true_peak_level: Option<f32>,
+    pub max_momentary_loudness: Option<f32>,
+    pub max_short_term_loudness: Option<f32>,
+    pub coding_history: String
+}
+
+impl BextChunk {
+    pub fn from_chunk_bytes(bext_bytes: &[u8]) -> Option<Self> {
+        // everything up to and including the reserved block is fixed size
+        if bext_bytes.len() < 602 {
+            return None;
+        }
+
+        let time_reference_low = u32::from_le_bytes([bext_bytes[338], bext_bytes[339], bext_bytes[340], bext_bytes[341]]) as u64;
+        let time_reference_high = u32::from_le_bytes([bext_bytes[342], bext_bytes[343], bext_bytes[344], bext_bytes[345]]) as u64;
+        let version = u16::from_le_bytes([bext_bytes[346], bext_bytes[347]]);
+
+        // loudness values are stored as hundredths and only exist from version 2 on
+        let loudness = |at: usize| {
+            if version < 2 {
+                return None;
+            }
+            let value = i16::from_le_bytes([bext_bytes[at], bext_bytes[at + 1]]);
+            if value == 0x7FFF {
+                None
+            } else {
+                Some(value as f32 / 100.0)
+            }
+        };
+
+        Some(BextChunk {
+            description: fixed_text(&bext_bytes[0..256]),
+            originator: fixed_text(&bext_bytes[256..288]),
+            originator_reference: fixed_text(&bext_bytes[288..320]),
+            origination_date: fixed_text(&bext_bytes[320..330]),
+            origination_time: fixed_text(&bext_bytes[330..338]),
+            time_reference: (time_reference_high << 32) | time_reference_low,
+            version,
+            loudness_value: loudness(412),
+            loudness_range: loudness(414),
+            max_true_peak_level: loudness(416),
+            max_momentary_loudness: loudness(418),
+            max_short_term_loudness: loudness(420),
+            coding_history: fixed_text(&bext_bytes[602..])
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct IxmlChunk {
+    pub project: Option<String>,
+    pub scene: Option<String>,
+    pub take: Option<String>,
+    pub tape: Option<String>,
+    pub note: Option<String>,
+    pub timecode_rate: Option<f64>,
+    pub xml: String
+}
+
+impl IxmlChunk {
+    pub fn from_chunk_bytes(ixml_bytes: &[u8]) -> Self {
+        let xml = fixed_text(ixml_bytes);
+
+        // TIMECODE_RATE is a fraction like "25/1" or "30000/1001"
+        let timecode_rate = xml_value(&xml, "TIMECODE_RATE").and_then(|rate| {
+            let (numerator, denominator) = rate.split_once('/').unwrap_or((&rate, "1"));
+            let numerator: f64 = numerator.trim().parse().ok()?;
+            let denominator: f64 = denominator.trim().parse().ok()?;
+
+            if numerator > 0.0 && denominator > 0.0 {
+                Some(numerator / denominator)
+            } else {
+                None
+            }
+        });
+
+        IxmlChunk {
+            project: xml_value(&xml, "PROJECT"),
+            scene: xml_value(&xml, "SCENE"),
+            take: xml_value(&xml, "TAKE"),
+            tape: xml_value(&xml, "TAPE"),
+            note: xml_value(&xml, "NOTE"),
+            timecode_rate,
+            xml
+        }
+    }
+}
+
+pub struct Timecode {
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+    frames: u64
+}
+
+impl Timecode {
+    // `samples` counts from midnight, like the bext time reference does
+    pub fn from_samples(samples: u64, sample_rate: u32, frame_rate: Option<f64>) -> Self {
+        let frame_rate = frame_rate.unwrap_or(DEFAULT_TIMECODE_RATE);
+        let sample_rate = sample_rate.max(1) as u64;
+
+        let total_seconds = samples / sample_rate;
+        let remaining_samples = samples % sample_rate;
+
+        Timecode {
+            hours: (total_seconds / 3600) % 24,
+            minutes: (total_seconds / 60) % 60,
+            seconds: total_seconds % 60,
+            frames: (remaining_samples as f64 * frame_rate / sample_rate as f64) as u64
+        }
+    }
+}
+
+impl Display for Timecode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds, self.frames)
+    }
+}
+
+// bext strings are ASCII padded with NULs to their field size
+fn fixed_text(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// iXML is flat enough that the first <TAG>value</TAG> occurrence is all we need
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    let value = xml[start..end].trim();
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BextChunk, IxmlChunk, Timecode};
+
+    fn bext_bytes(version: u16, time_reference: u64, loudness: [i16; 5], coding_history: &str) -> Vec<u8> {
+        let mut bytes = vec![0u8; 602];
+        bytes[0..11].copy_from_slice(b"Description");
+        bytes[256..262].copy_from_slice(b"Origin");
+        bytes[320..330].copy_from_slice(b"2024-01-31");
+        bytes[338..346].copy_from_slice(&time_reference.to_le_bytes());
+        bytes[346..348].copy_from_slice(&version.to_le_bytes());
+        for (index, value) in loudness.iter().enumerate() {
+            bytes[412 + index * 2..414 + index * 2].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(coding_history.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn reads_bext() {
+        let bext = BextChunk::from_chunk_bytes(&bext_bytes(2, 0x1_0000_0010, [-2300, 650, -100, 0x7FFF, 0x7FFF], "A=PCM,F=48000\r\n")).unwrap();
+
+        assert_eq!(bext.description, "Description");
+        assert_eq!(bext.originator, "Origin");
+        assert_eq!(bext.origination_date, "2024-01-31");
+        assert_eq!(bext.time_reference, 0x1_0000_0010);
+        assert_eq!((bext.loudness_value, bext.loudness_range, bext.max_true_peak_level), (Some(-23.0), Some(6.5), Some(-1.0)));
+        assert_eq!(bext.max_momentary_loudness, None);
+        assert_eq!(bext.coding_history, "A=PCM,F=48000");
+
+        // version 1 has no loudness fields, the bytes are reserved
+        let bext = BextChunk::from_chunk_bytes(&bext_bytes(1, 0, [-2300, 0, 0, 0, 0], "")).unwrap();
+        assert_eq!(bext.loudness_value, None);
+    }
+
+    #[test]
+    fn reads_ixml() {
+        let ixml = IxmlChunk::from_chunk_bytes(b"<BWFXML><PROJECT> Film </PROJECT><SCENE>12A</SCENE><NOTE></NOTE><TIMECODE_RATE>30000/1001</TIMECODE_RATE></BWFXML>\0\0");
+
+        assert_eq!(ixml.project.as_deref(), Some("Film"));
+        assert_eq!(ixml.scene.as_deref(), Some("12A"));
+        assert_eq!(ixml.note, None);
+        assert_eq!(ixml.timecode_rate, Some(30000.0 / 1001.0));
+    }
+
+    #[test]
+    fn formats_timecode() {
+        let samples = (3600 + 60 + 1) * 48000 + 24000;
+
+        assert_eq!(Timecode::from_samples(samples, 48000, None).to_string(), "01:01:01:12");
+        assert_eq!(Timecode::from_samples(samples, 48000, Some(30.0)).to_string(), "01:01:01:15");
+        // past midnight wraps around
+        assert_eq!(Timecode::from_samples(25 * 3600 * 48000, 48000, None).to_string(), "01:00:00:00");
+    }
+
+    #[test]
+    fn rejects_malformed_chunks() {
+        assert!(BextChunk::from_chunk_bytes(&[0u8; 601]).is_none());
+
+        let ixml = IxmlChunk::from_chunk_bytes(b"<PROJECT>Film<TIMECODE_RATE>0/1</TIMECODE_RATE><TAKE>3/x");
+        assert_eq!(ixml.project, None);
+        assert_eq!(ixml.timecode_rate, None);
+        assert_eq!(ixml.take, None);
+        assert_eq!(Timecode::from_samples(100, 0, Some(25.0)).to_string(), "00:01:40:00");
+    }
+}
@@ -0,0 +1,188 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use crate::wav::{
+    default_channel_mask, SPEAKER_BACK_CENTER, SPEAKER_BACK_LEFT, SPEAKER_BACK_RIGHT, SPEAKER_FRONT_CENTER, SPEAKER_FRONT_LEFT,
+    SPEAKER_FRONT_LEFT_OF_CENTER, SPEAKER_FRONT_RIGHT, SPEAKER_FRONT_RIGHT_OF_CENTER, SPEAKER_SIDE_LEFT, SPEAKER_SIDE_RIGHT,
+    SPEAKER_TOP_BACK_CENTER, SPEAKER_TOP_BACK_LEFT, SPEAKER_TOP_BACK_RIGHT, SPEAKER_TOP_CENTER, SPEAKER_TOP_FRONT_CENTER,
+    SPEAKER_TOP_FRONT_LEFT, SPEAKER_TOP_FRONT_RIGHT
+};
+
+// the ITU-R BS.775 gain for folding one speaker into a pair of others
+const MINUS_3DB: f32 = FRAC_1_SQRT_2;
+// a speaker is folded this many times at most before it's dropped, which also breaks fold cycles
+const MAX_FOLD_DEPTH: usize = 4;
+
+// Mixes frames in the file's channel layout down or up to the device's channels. Speakers the device has
+// are passed straight through, the others are folded into the nearest ones it does have. Mono is copied to
+// both front speakers, LFE is dropped like ITU downmixes do. Channels without a speaker position in the
+// mask go to the device channel with the same index, if there is one.
+pub struct ChannelMap {
+    max_source_channels: usize,
+    source_channels: usize,
+    device_channels: usize,
+    device_mask: u32,
+    // a row of max_source_channels gains for every device channel
+    gains: Vec<f32>
+}
+
+impl ChannelMap {
+    // devices only report a channel count, their speakers are assumed to follow the WAVE default layouts
+    pub fn new(max_source_channels: usize, device_channels: usize) -> Self {
+        ChannelMap {
+            max_source_channels,
+            source_channels: 0,
+            device_channels,
+            device_mask: default_channel_mask(device_channels as u16),
+            gains: vec![0.0; max_source_channels * device_channels]
+        }
+    }
+
+    // recomputes the gains for another source without reallocating
+    pub fn configure(&mut self, channels: usize, channel_mask: u32) {
+        self.source_channels = channels.min(self.max_source_channels);
+        self.gains.fill(0.0);
+
+        if self.source_channels == 1 && self.has_speakers(SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT) {
+            self.add_gain(SPEAKER_FRONT_LEFT, 0, 1.0);
+            self.add_gain(SPEAKER_FRONT_RIGHT, 0, 1.0);
+            return;
+        }
+
+        let mut remaining_mask = if self.device_mask == 0 { 0 } else { channel_mask };
+        for channel in 0..self.source_channels {
+            if remaining_mask == 0 {
+                if channel < self.device_channels {
+                    self.gains[channel * self.max_source_channels + channel] = 1.0;
+                }
+                continue;
+            }
+
+            let speaker = remaining_mask & remaining_mask.wrapping_neg();
+            remaining_mask &= !speaker;
+            self.route(speaker, channel, 1.0, 0);
+        }
+    }
+
+    // writes one device frame from one source frame
+    pub fn apply(&self, input: &[f32], output: &mut [f32]) {
+        for (device_channel, sample) in output.iter_mut().enumerate() {
+            let row = &self.gains[device_channel * self.max_source_channels..][..self.source_channels];
+            *sample = row.iter().zip(input).map(|(gain, input)| gain * input).sum();
+        }
+    }
+
+    fn route(&mut self, speaker: u32, channel: usize, gain: f32, depth: usize) {
+        if self.has_speakers(speaker) {
+            self.add_gain(speaker, channel, gain);
+            return;
+        }
+        if depth == MAX_FOLD_DEPTH {
+            return;
+        }
+
+        // the first fold whose speakers all exist wins, otherwise the last and widest one is folded further
+        let folds = fold_targets(speaker);
+        let fold = folds
+            .iter()
+            .find(|fold| fold.iter().all(|(target, _)| self.has_speakers(*target)))
+            .or(folds.last());
+
+        for (target, fold_gain) in fold.copied().unwrap_or_default() {
+            self.route(*target, channel, gain * fold_gain, depth + 1);
+        }
+    }
+
+    fn has_speakers(&self, speakers: u32) -> bool {
+        self.device_mask & speakers == speakers
+    }
+
+    // device channels are ordered like the bits of their mask
+    fn add_gain(&mut self, speaker: u32, channel: usize, gain: f32) {
+        let device_channel = (self.device_mask & (speaker - 1)).count_ones() as usize;
+        self.gains[device_channel * self.max_source_channels + channel] += gain;
+    }
+}
+
+// where a speaker's signal goes on a device that lacks it, in order of preference
+fn fold_targets(speaker: u32) -> &'static [&'static [(u32, f32)]] {
+    match speaker {
+        SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT => &[&[(SPEAKER_FRONT_CENTER, MINUS_3DB)]],
+        SPEAKER_FRONT_CENTER => &[&[(SPEAKER_FRONT_LEFT, MINUS_3DB), (SPEAKER_FRONT_RIGHT, MINUS_3DB)]],
+        SPEAKER_FRONT_LEFT_OF_CENTER => &[&[(SPEAKER_FRONT_LEFT, 1.0)]],
+        SPEAKER_FRONT_RIGHT_OF_CENTER => &[&[(SPEAKER_FRONT_RIGHT, 1.0)]],
+        SPEAKER_BACK_LEFT => &[&[(SPEAKER_SIDE_LEFT, 1.0)], &[(SPEAKER_FRONT_LEFT, MINUS_3DB)]],
+        SPEAKER_BACK_RIGHT => &[&[(SPEAKER_SIDE_RIGHT, 1.0)], &[(SPEAKER_FRONT_RIGHT, MINUS_3DB)]],
+        SPEAKER_SIDE_LEFT => &[&[(SPEAKER_BACK_LEFT, 1.0)], &[(SPEAKER_FRONT_LEFT, MINUS_3DB)]],
+        SPEAKER_SIDE_RIGHT => &[&[(SPEAKER_BACK_RIGHT, 1.0)], &[(SPEAKER_FRONT_RIGHT, MINUS_3DB)]],
+        SPEAKER_BACK_CENTER => &[
+            &[(SPEAKER_BACK_LEFT, MINUS_3DB), (SPEAKER_BACK_RIGHT, MINUS_3DB)],
+            &[(SPEAKER_SIDE_LEFT, MINUS_3DB), (SPEAKER_SIDE_RIGHT, MINUS_3DB)],
+            &[(SPEAKER_FRONT_LEFT, 0.5), (SPEAKER_FRONT_RIGHT, 0.5)]
+        ],
+        SPEAKER_TOP_FRONT_LEFT => &[&[(SPEAKER_FRONT_LEFT, 1.0)]],
+        SPEAKER_TOP_FRONT_CENTER | SPEAKER_TOP_CENTER => &[&[(SPEAKER_FRONT_CENTER, 1.0)]],
+        SPEAKER_TOP_FRONT_RIGHT => &[&[(SPEAKER_FRONT_RIGHT, 1.0)]],
+        SPEAKER_TOP_BACK_LEFT => &[&[(SPEAKER_BACK_LEFT, 1.0)]],
+        SPEAKER_TOP_BACK_CENTER => &[&[(SPEAKER_BACK_CENTER, 1.0)]],
+        SPEAKER_TOP_BACK_RIGHT => &[&[(SPEAKER_BACK_RIGHT, 1.0)]],
+        // LFE and reserved bits
+        _ => &[]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelMap, MINUS_3DB};
+    use crate::wav::SPEAKER_BACK_CENTER;
+
+    fn map(device_channels: usize, channels: usize, channel_mask: u32, input: &[f32]) -> Vec<f32> {
+        let mut channel_map = ChannelMap::new(8, device_channels);
+        channel_map.configure(channels, channel_mask);
+
+        let mut output = vec![f32::NAN; device_channels];
+        channel_map.apply(input, &mut output);
+        output
+    }
+
+    fn assert_near(output: &[f32], expected: &[f32]) {
+        assert_eq!(output.len(), expected.len());
+        assert!(output.iter().zip(expected).all(|(output, expected)| (output - expected).abs() < 1e-6), "{:?} != {:?}", output, expected);
+    }
+
+    #[test]
+    fn downmixes_itu_5_1_to_stereo() {
+        // L, R, C, LFE, Ls, Rs, the LFE is dropped
+        let output = map(2, 6, 0x3F, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_near(&output, &[1.0 + MINUS_3DB * (3.0 + 5.0), 2.0 + MINUS_3DB * (3.0 + 6.0)]);
+
+        // and side surrounds of 7.1 land on the 5.1 back speakers
+        let output = map(6, 8, 0x63F, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_near(&output, &[1.0, 2.0, 3.0, 4.0, 5.0 + 7.0, 6.0 + 8.0]);
+    }
+
+    #[test]
+    fn maps_mono_and_stereo() {
+        assert_near(&map(2, 1, 0x4, &[0.5]), &[0.5, 0.5]);
+        assert_near(&map(1, 1, 0x4, &[0.5]), &[0.5]);
+        assert_near(&map(1, 2, 0x3, &[0.5, -0.5]), &[0.0]);
+        assert_near(&map(1, 2, 0x3, &[0.5, 0.5]), &[2.0 * MINUS_3DB * 0.5]);
+        assert_near(&map(6, 2, 0x3, &[0.5, -0.5]), &[0.5, -0.5, 0.0, 0.0, 0.0, 0.0]);
+
+        // a back centre folds into the back pair where there is one, otherwise half into each front speaker
+        assert_near(&map(4, 2, 0x1 | SPEAKER_BACK_CENTER, &[0.0, 1.0]), &[0.0, 0.0, MINUS_3DB, MINUS_3DB]);
+        assert_near(&map(2, 3, 0x3 | SPEAKER_BACK_CENTER, &[0.0, 0.0, 1.0]), &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn maps_channels_without_positions_by_index() {
+        // no mask at all, or fewer speakers than channels
+        assert_near(&map(2, 4, 0, &[1.0, 2.0, 3.0, 4.0]), &[1.0, 2.0]);
+        assert_near(&map(3, 3, 0x3, &[1.0, 2.0, 3.0]), &[1.0, 2.0, 3.0]);
+        // a device without a default layout takes the channels as they are
+        assert_near(&map(7, 6, 0x3F, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 0.0]);
+        // reserved bits have nowhere to go
+        assert_near(&map(2, 3, 0x8000_0003, &[1.0, 2.0, 3.0]), &[1.0, 2.0]);
+        // more channels than the map was made for are dropped
+        assert_near(&map(2, 10, 0, &[1.0; 10]), &[1.0, 1.0]);
+    }
+}
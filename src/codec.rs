@@ -0,0 +1,99 @@
+use crate::adpcm::{ImaAdpcm, MsAdpcm};
+use crate::sample_format::SampleFormat;
+use crate::wav::{Endianness, FmtSubChunk, WAVE_FORMAT_ADPCM, WAVE_FORMAT_IMA_ADPCM, WavError};
+
+// How the bytes in a data chunk turn into samples. PCM style formats decode one frame per block,
+// ADPCM formats decode a whole block of compressed nibbles at once.
+#[derive(Debug, Clone)]
+pub enum Codec {
+    Pcm {
+        sample_format: SampleFormat,
+        channels: usize,
+        endianness: Endianness
+    },
+    ImaAdpcm(ImaAdpcm),
+    MsAdpcm(MsAdpcm)
+}
+
+impl Codec {
+    pub fn from_fmt(fmt: &FmtSubChunk, endianness: Endianness) -> Result<Self, WavError> {
+        match (fmt.format_code(), endianness) {
+            (WAVE_FORMAT_IMA_ADPCM, Endianness::Little) => Ok(Codec::ImaAdpcm(ImaAdpcm::from_fmt(fmt)?)),
+            (WAVE_FORMAT_ADPCM, Endianness::Little) => Ok(Codec::MsAdpcm(MsAdpcm::from_fmt(fmt)?)),
+            (WAVE_FORMAT_IMA_ADPCM, _) | (WAVE_FORMAT_ADPCM, _) => Err(WavError::UnsupportedFormat(fmt.format_code())),
+            _ => Ok(Codec::Pcm {
+                sample_format: SampleFormat::from_fmt(fmt)?,
+                channels: fmt.channels as usize,
+                endianness
+            })
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        match self {
+            Codec::Pcm { channels, .. } => *channels,
+            Codec::ImaAdpcm(ima) => ima.channels,
+            Codec::MsAdpcm(ms) => ms.channels
+        }
+    }
+
+    pub fn block_align(&self) -> usize {
+        match self {
+            Codec::Pcm { sample_format, channels, .. } => sample_format.bytes_per_sample() * channels,
+            Codec::ImaAdpcm(ima) => ima.block_align,
+            Codec::MsAdpcm(ms) => ms.block_align
+        }
+    }
+
+    pub fn frames_per_block(&self) -> usize {
+        match self {
+            Codec::Pcm { .. } => 1,
+            Codec::ImaAdpcm(ima) => ima.samples_per_block,
+            Codec::MsAdpcm(ms) => ms.samples_per_block
+        }
+    }
+
+    pub fn frame_count(&self, data_size: u64) -> u64 {
+        let block_align = self.block_align() as u64;
+        let frames_per_block = self.frames_per_block() as u64;
+
+        match self {
+            Codec::Pcm { .. } => data_size / block_align,
+            // a shorter trailing block still holds its header samples
+            _ => (data_size / block_align) * frames_per_block + self.partial_block_frames((data_size % block_align) as usize) as u64
+        }
+    }
+
+    // decodes one block into interleaved samples, the trailing block of a data chunk may be shorter than block_align
+    pub fn decode_block(&self, block: &[u8], output: &mut Vec<f32>) {
+        match self {
+            Codec::Pcm { sample_format, endianness, .. } => {
+                output.clear();
+                let bytes_per_sample = sample_format.bytes_per_sample();
+                for sample_bytes in block.chunks_exact(bytes_per_sample) {
+                    let sample = match endianness {
+                        Endianness::Little => sample_format.decode(sample_bytes),
+                        Endianness::Big => {
+                            // sample decoding works on little-endian bytes, flip RIFX samples first
+                            let mut little_endian = [0u8; 8];
+                            little_endian[..bytes_per_sample].copy_from_slice(sample_bytes);
+                            little_endian[..bytes_per_sample].reverse();
+                            sample_format.decode(&little_endian[..bytes_per_sample])
+                        }
+                    };
+                    output.push(sample);
+                }
+            }
+            Codec::ImaAdpcm(ima) => ima.decode_block(block, output),
+            Codec::MsAdpcm(ms) => ms.decode_block(block, output)
+        }
+    }
+
+    fn partial_block_frames(&self, block_size: usize) -> usize {
+        match self {
+            Codec::Pcm { .. } => 0,
+            Codec::ImaAdpcm(ima) => ima.frames_in_block(block_size),
+            Codec::MsAdpcm(ms) => ms.frames_in_block(block_size)
+        }
+    }
+}
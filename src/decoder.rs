@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::aiff::{AiffDecoder, AiffError};
+use crate::flac::FlacDecoder;
+use crate::markers::{Marker, SampleLoop};
+use crate::metadata::Metadata;
+use crate::mp3::Mp3Decoder;
+use crate::ogg::OggDecoder;
+use crate::wav::{WavDecoder, WavError};
+
+// Everything the playlist, gui and player need to know about a track, whichever format it is stored in
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct StreamInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub channel_mask: u32,
+    pub frames: u64,
+    pub markers: Vec<Marker>,
+    pub loops: Vec<SampleLoop>,
+    // samples since midnight of the first frame and the timecode rate to show it in, broadcast WAVs carry these
+    pub time_reference: Option<u64>,
+    pub timecode_rate: Option<f64>,
+    // format specific lines for the track info view
+    pub details: Vec<(String, String)>
+}
+
+// A format backend. Decoders are opened on the gui thread and then moved to the stream's decoder thread.
+pub trait Decoder: Send {
+    fn open(path: &Path) -> Result<Self, DecoderError> where Self: Sized;
+
+    fn info(&self) -> &StreamInfo;
+
+    fn metadata(&self) -> &Metadata;
+
+    // appends up to `max_frames` interleaved frames to `samples` and returns how many were added, 0 once the track ended
+    fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError>;
+
+    // the next read starts exactly at `frame`
+    fn seek(&mut self, frame: u64) -> Result<(), DecoderError>;
+}
+
+// the player's buffers are sized for this many channels up front, tracks with more are rejected when opened
+pub const MAX_CHANNELS: usize = 32;
+
+const SUPPORTED_EXTENSIONS: [&str; 9] = ["wav", "w64", "aif", "aiff", "aifc", "flac", "mp3", "ogg", "opus"];
+
+// picks the backend from the file extension
+pub fn open(path: &Path) -> Result<Box<dyn Decoder>, DecoderError> {
+    let decoder: Box<dyn Decoder> = match extension(path).as_deref() {
+        Some("wav") | Some("w64") => Box::new(WavDecoder::open(path)?),
+        Some("aif") | Some("aiff") | Some("aifc") => Box::new(AiffDecoder::open(path)?),
+        Some("flac") => Box::new(FlacDecoder::open(path)?),
+        Some("mp3") => Box::new(Mp3Decoder::open(path)?),
+        Some("ogg") | Some("opus") => Box::new(OggDecoder::open(path)?),
+        _ => return Err(DecoderError::UnsupportedFile)
+    };
+
+    let channels = decoder.info().channels;
+    if channels as usize > MAX_CHANNELS {
+        return Err(DecoderError::TooManyChannels(channels));
+    }
+
+    Ok(decoder)
+}
+
+pub fn is_supported(path: &Path) -> bool {
+    extension(path).is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+#[derive(Debug)]
+pub enum DecoderError {
+    UnsupportedFile,
+    TooManyChannels(u16),
+    Io(std::io::Error),
+    Wav(WavError),
+    Aiff(AiffError),
+    Flac(claxon::Error),
+    Mp3(&'static str),
+    Ogg(&'static str),
+    Vorbis(lewton::VorbisError),
+    Opus(String)
+}
+
+impl Display for DecoderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecoderError::UnsupportedFile => write!(f, "unsupported file type"),
+            DecoderError::TooManyChannels(channels) => write!(f, "{} channels, at most {} are supported", channels, MAX_CHANNELS),
+            DecoderError::Io(err) => write!(f, "I/O error: {}", err),
+            DecoderError::Wav(err) => write!(f, "{}", err),
+            DecoderError::Aiff(err) => write!(f, "{}", err),
+            DecoderError::Flac(err) => write!(f, "FLAC error: {}", err),
+            DecoderError::Mp3(err) => write!(f, "MP3 error: {}", err),
+            DecoderError::Ogg(err) => write!(f, "Ogg error: {}", err),
+            DecoderError::Vorbis(err) => write!(f, "Vorbis error: {}", err),
+            DecoderError::Opus(err) => write!(f, "Opus error: {}", err)
+        }
+    }
+}
+
+impl Error for DecoderError {}
+
+impl From<WavError> for DecoderError {
+    fn from(err: WavError) -> Self {
+        DecoderError::Wav(err)
+    }
+}
+
+impl From<AiffError> for DecoderError {
+    fn from(err: AiffError) -> Self {
+        DecoderError::Aiff(err)
+    }
+}
+
+impl From<std::io::Error> for DecoderError {
+    fn from(err: std::io::Error) -> Self {
+        DecoderError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{is_supported, open, DecoderError};
+    use crate::sample_format::SampleFormat;
+    use crate::wav::WavError;
+    use crate::wav_writer::{WavSpec, WavWriter};
+
+    fn temp_path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.{}", name, std::process::id(), extension))
+    }
+
+    // a stereo ramp, the left channel counts up in 1/1024 steps and the right one down
+    fn write_ramp(path: &Path, frames: usize) {
+        let mut writer = WavWriter::create(path, WavSpec::new(2, 32000, SampleFormat::I16)).unwrap();
+        let samples: Vec<f32> = (0..frames).flat_map(|frame| [frame as f32 / 1024.0, -(frame as f32) / 1024.0]).collect();
+        writer.write_samples(&samples).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn opens_by_extension() {
+        let path = temp_path("decoder-open", "WAV");
+        write_ramp(&path, 1000);
+
+        let result = open(&path).map(|mut decoder| {
+            let info = decoder.info().clone();
+            let mut samples = Vec::new();
+            decoder.seek(500).unwrap();
+            let frames = decoder.read_frames(&mut samples, 4).unwrap();
+            (info, frames, samples)
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        let (info, frames, samples) = result.unwrap();
+        assert_eq!((info.sample_rate, info.channels, info.frames), (32000, 2, 1000));
+        assert_eq!(frames, 4);
+        assert_eq!(samples[0..2], [500.0 / 1024.0, -500.0 / 1024.0]);
+        assert!(is_supported(Path::new("song.FLAC")));
+        assert!(!is_supported(Path::new("notes.txt")));
+    }
+
+    #[test]
+    fn rejects_unsupported_and_malformed_files() {
+        assert!(matches!(open(Path::new("notes.txt")), Err(DecoderError::UnsupportedFile)));
+
+        let path = temp_path("decoder-garbage", "wav");
+        std::fs::write(&path, b"not a wave file at all").unwrap();
+        let result = open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(DecoderError::Wav(WavError::UnknownContainer(_)))));
+
+        // more channels than the player's buffers hold
+        let path = temp_path("decoder-channels", "wav");
+        let mut writer = WavWriter::create(&path, WavSpec::new(33, 32000, SampleFormat::I16)).unwrap();
+        writer.write_samples(&[0.0; 33 * 10]).unwrap();
+        writer.finalize().unwrap();
+        let result = open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(DecoderError::TooManyChannels(33))));
+    }
+}
@@ -0,0 +1,72 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Quantizes the player's f32 output for devices that only take 16-bit integer samples. Two uniform values
+// are subtracted to get triangular (TPDF) noise of +-1 LSB, which decorrelates the rounding error from the
+// signal so quiet passages fade into a steady hiss instead of distorting. Samples past full scale are clipped,
+// downmixes and lossy decoders can overshoot slightly.
+pub struct Dither {
+    rng: StdRng
+}
+
+impl Dither {
+    pub fn new() -> Self {
+        Dither {
+            rng: StdRng::from_entropy()
+        }
+    }
+
+    pub fn quantize_i16(&mut self, sample: f32) -> i16 {
+        let noise = self.rng.gen::<f32>() - self.rng.gen::<f32>();
+        (sample * 32768.0 + noise).round().clamp(-32768.0, 32767.0) as i16
+    }
+
+    // offset binary, 32768 is silence
+    pub fn quantize_u16(&mut self, sample: f32) -> u16 {
+        (self.quantize_i16(sample) as i32 + 32768) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dither;
+
+    #[test]
+    fn adds_at_most_one_lsb_of_noise() {
+        let mut dither = Dither::new();
+
+        let values: Vec<i16> = (0..10000).map(|_| dither.quantize_i16(0.25)).collect();
+        assert!(values.iter().all(|value| (8191..=8193).contains(value)));
+
+        // silence turns into -1, 0 and +1, averaging out to nothing
+        let values: Vec<i16> = (0..10000).map(|_| dither.quantize_i16(0.0)).collect();
+        assert!(values.iter().all(|value| (-1..=1).contains(value)));
+        assert!(values.contains(&-1) && values.contains(&1));
+        let mean = values.iter().map(|value| *value as f64).sum::<f64>() / values.len() as f64;
+        assert!(mean.abs() < 0.05);
+
+        // half an LSB is kept on average instead of always rounding one way
+        let mean = (0..10000).map(|_| dither.quantize_i16(0.5 / 32768.0) as f64).sum::<f64>() / 10000.0;
+        assert!((mean - 0.5).abs() < 0.05);
+
+        let values: Vec<u16> = (0..1000).map(|_| dither.quantize_u16(0.0)).collect();
+        assert!(values.iter().all(|value| (32767..=32769).contains(value)));
+    }
+
+    #[test]
+    fn clips_past_full_scale() {
+        let mut dither = Dither::new();
+
+        for _ in 0..1000 {
+            assert_eq!(dither.quantize_i16(1.5), i16::MAX);
+            assert_eq!(dither.quantize_i16(-2.0), i16::MIN);
+            assert_eq!(dither.quantize_i16(f32::INFINITY), i16::MAX);
+            assert_eq!(dither.quantize_i16(f32::NEG_INFINITY), i16::MIN);
+            assert!(dither.quantize_i16(1.0) >= 32766);
+            assert_eq!(dither.quantize_u16(1.5), u16::MAX);
+            assert_eq!(dither.quantize_u16(-1.5), 0);
+        }
+        // a broken decoder's NaN comes out as silence
+        assert_eq!(dither.quantize_i16(f32::NAN), 0);
+    }
+}
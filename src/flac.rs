@@ -0,0 +1,442 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use claxon::frame::FrameReader;
+use claxon::input::BufferedReader;
+
+use crate::decoder::{Decoder, DecoderError, StreamInfo};
+use crate::metadata::{Metadata, Picture};
+
+const BLOCK_STREAMINFO: u8 = 0;
+const BLOCK_SEEKTABLE: u8 = 3;
+const BLOCK_VORBIS_COMMENT: u8 = 4;
+const BLOCK_PICTURE: u8 = 6;
+
+// seek table entries with this sample number are placeholders
+const PLACEHOLDER_SEEK_POINT: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct SeekPoint {
+    sample: u64,
+    // bytes from the first frame header
+    offset: u64
+}
+
+// Decoder backend for native FLAC files. The metadata blocks are read here, claxon decodes the frames.
+pub struct FlacDecoder {
+    info: StreamInfo,
+    metadata: Metadata,
+    frames: Option<FrameReader<BufferedReader<File>>>,
+    audio_offset: u64,
+    bits_per_sample: u32,
+    seek_points: Vec<SeekPoint>,
+    block_buffer: Vec<i32>,
+    decoded: Vec<f32>,
+    decoded_index: usize,
+    // set by a seek, frames before it are decoded and dropped
+    seek_target: Option<u64>
+}
+
+impl Decoder for FlacDecoder {
+    fn open(path: &Path) -> Result<Self, DecoderError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        // some taggers put an ID3v2 tag in front of the stream
+        if &magic[0..3] == b"ID3" {
+            let mut header = [0u8; 6];
+            reader.read_exact(&mut header)?;
+            let tag_size = header[2..6].iter().fold(0u64, |size, byte| (size << 7) | (*byte & 0x7F) as u64);
+            reader.seek(SeekFrom::Current(tag_size as i64))?;
+            reader.read_exact(&mut magic)?;
+        }
+
+        if &magic != b"fLaC" {
+            return Err(DecoderError::Flac(claxon::Error::FormatError("invalid stream marker")));
+        }
+
+        let mut stream_info: Option<(u32, u32, u32, u64)> = None;
+        let mut seek_points: Vec<SeekPoint> = Vec::new();
+        let mut metadata = Metadata::default();
+
+        loop {
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header)?;
+
+            let is_last = header[0] & 0x80 != 0;
+            let block_type = header[0] & 0x7F;
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+            let mut block = vec![0u8; length];
+            reader.read_exact(&mut block)?;
+
+            match block_type {
+                BLOCK_STREAMINFO => stream_info = read_stream_info(&block),
+                BLOCK_SEEKTABLE => seek_points = read_seek_table(&block),
+                BLOCK_VORBIS_COMMENT => metadata = Metadata::from_vorbis_comment(&block).or(metadata),
+                BLOCK_PICTURE => {
+                    // the front cover wins over any other picture
+                    let picture = Picture::from_flac_picture(&block);
+                    let is_front_cover = picture.as_ref().is_some_and(|picture| picture.picture_type == 3);
+                    if metadata.picture.is_none() || is_front_cover {
+                        metadata.picture = picture.or(metadata.picture);
+                    }
+                }
+                _ => {}
+            }
+
+            if is_last {
+                break;
+            }
+        }
+
+        let Some((sample_rate, channels, bits_per_sample, total_samples)) = stream_info else {
+            return Err(DecoderError::Flac(claxon::Error::FormatError("missing streaminfo block")));
+        };
+        // playback would never advance at a zero rate
+        if sample_rate == 0 {
+            return Err(DecoderError::Flac(claxon::Error::FormatError("invalid sample rate")));
+        }
+
+        let audio_offset = reader.stream_position()?;
+        let file = reader.into_inner();
+
+        let info = StreamInfo {
+            sample_rate,
+            channels: channels as u16,
+            channel_mask: flac_channel_mask(channels),
+            frames: total_samples,
+            markers: Vec::new(),
+            loops: Vec::new(),
+            time_reference: None,
+            timecode_rate: None,
+            details: vec![
+                (String::from("Format"), format!("FLAC, {} bit", bits_per_sample)),
+                (String::from("Seek points"), seek_points.len().to_string())
+            ]
+        };
+
+        let mut decoder = FlacDecoder {
+            info,
+            metadata,
+            frames: None,
+            audio_offset,
+            bits_per_sample,
+            seek_points,
+            block_buffer: Vec::new(),
+            decoded: Vec::new(),
+            decoded_index: 0,
+            seek_target: None
+        };
+        decoder.restart(file, 0)?;
+
+        Ok(decoder)
+    }
+
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError> {
+        let channels = self.info.channels as usize;
+
+        if self.decoded_index >= self.decoded.len() && !self.decode_frame()? {
+            return Ok(0);
+        }
+
+        let frames = ((self.decoded.len() - self.decoded_index) / channels).min(max_frames);
+        let end = self.decoded_index + frames * channels;
+        samples.extend_from_slice(&self.decoded[self.decoded_index..end]);
+        self.decoded_index = end;
+
+        Ok(frames)
+    }
+
+    // jumps to the last seek point at or before `frame` and decodes forward from there,
+    // without a seek table that means decoding from the first frame
+    fn seek(&mut self, frame: u64) -> Result<(), DecoderError> {
+        let seek_point = self.seek_points
+            .iter()
+            .take_while(|seek_point| seek_point.sample <= frame)
+            .last()
+            .copied()
+            .unwrap_or(SeekPoint { sample: 0, offset: 0 });
+
+        let file = match self.frames.take() {
+            Some(frames) => frames.into_inner().into_inner(),
+            None => return Err(DecoderError::Flac(claxon::Error::FormatError("stream lost after a failed seek")))
+        };
+
+        self.restart(file, seek_point.offset)?;
+        self.seek_target = Some(frame);
+
+        Ok(())
+    }
+}
+
+impl FlacDecoder {
+    fn restart(&mut self, mut file: File, offset: u64) -> Result<(), DecoderError> {
+        file.seek(SeekFrom::Start(self.audio_offset + offset))?;
+
+        self.frames = Some(FrameReader::new(BufferedReader::new(file)));
+        self.decoded.clear();
+        self.decoded_index = 0;
+        self.seek_target = None;
+
+        Ok(())
+    }
+
+    // decodes the next frame into `decoded`, returns false at the end of the stream
+    fn decode_frame(&mut self) -> Result<bool, DecoderError> {
+        let Some(frames) = self.frames.as_mut() else {
+            return Ok(false);
+        };
+
+        let scale = 1.0 / (1u64 << (self.bits_per_sample - 1)) as f32;
+
+        loop {
+            let buffer = std::mem::take(&mut self.block_buffer);
+            let Some(block) = frames.read_next_or_eof(buffer).map_err(DecoderError::Flac)? else {
+                return Ok(false);
+            };
+
+            let block_frames = block.duration() as u64;
+            let first_frame = block.time();
+
+            // still before the seek target
+            if let Some(target) = self.seek_target {
+                if first_frame + block_frames <= target {
+                    self.block_buffer = block.into_buffer();
+                    continue;
+                }
+            }
+
+            let channels = block.channels();
+            self.decoded.clear();
+            for frame in 0..block.duration() {
+                for channel in 0..channels {
+                    self.decoded.push(block.sample(channel, frame) as f32 * scale);
+                }
+            }
+
+            self.decoded_index = match self.seek_target.take() {
+                Some(target) if target > first_frame => (target - first_frame) as usize * channels as usize,
+                _ => 0
+            };
+
+            self.block_buffer = block.into_buffer();
+            return Ok(true);
+        }
+    }
+}
+
+// sample rate, channels, bits per sample and total samples
+fn read_stream_info(block: &[u8]) -> Option<(u32, u32, u32, u64)> {
+    if block.len() < 34 {
+        return None;
+    }
+
+    let sample_rate = ((block[10] as u32) << 12) | ((block[11] as u32) << 4) | ((block[12] as u32) >> 4);
+    let channels = ((block[12] >> 1) & 0x07) as u32 + 1;
+    let bits_per_sample = ((((block[12] & 0x01) << 4) | (block[13] >> 4)) as u32) + 1;
+    let total_samples = (((block[13] & 0x0F) as u64) << 32) | u32::from_be_bytes([block[14], block[15], block[16], block[17]]) as u64;
+
+    Some((sample_rate, channels, bits_per_sample, total_samples))
+}
+
+fn read_seek_table(block: &[u8]) -> Vec<SeekPoint> {
+    block
+        .chunks_exact(18)
+        .map(|point| SeekPoint {
+            sample: u64::from_be_bytes([point[0], point[1], point[2], point[3], point[4], point[5], point[6], point[7]]),
+            offset: u64::from_be_bytes([point[8], point[9], point[10], point[11], point[12], point[13], point[14], point[15]])
+        })
+        .filter(|point| point.sample != PLACEHOLDER_SEEK_POINT)
+        .collect()
+}
+
+// FLAC fixes the speaker layout by channel count, in the same order as WAVE_FORMAT_EXTENSIBLE
+fn flac_channel_mask(channels: u32) -> u32 {
+    match channels {
+        1 => 0x4,
+        2 => 0x3,
+        3 => 0x7,
+        4 => 0x33,
+        5 => 0x37,
+        6 => 0x3F,
+        7 => 0x70F,
+        8 => 0x63F,
+        _ => 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{flac_channel_mask, read_seek_table, read_stream_info, FlacDecoder, BLOCK_PICTURE, BLOCK_SEEKTABLE, BLOCK_STREAMINFO, BLOCK_VORBIS_COMMENT, PLACEHOLDER_SEEK_POINT};
+    use crate::decoder::{Decoder, DecoderError};
+
+    const BLOCK_SIZE: usize = 16;
+
+    fn stream_info(sample_rate: u32, channels: u32, bits_per_sample: u32, total_samples: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend((BLOCK_SIZE as u16).to_be_bytes());
+        bytes.extend((BLOCK_SIZE as u16).to_be_bytes());
+        bytes.extend([0u8; 6]);
+        let packed = ((sample_rate as u64) << 44) | (((channels - 1) as u64) << 41) | (((bits_per_sample - 1) as u64) << 36) | total_samples;
+        bytes.extend(packed.to_be_bytes());
+        bytes.extend([0u8; 16]);
+        bytes
+    }
+
+    fn metadata_block(block_type: u8, is_last: bool, body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![block_type | if is_last { 0x80 } else { 0 }];
+        bytes.extend(&(body.len() as u32).to_be_bytes()[1..4]);
+        bytes.extend(body);
+        bytes
+    }
+
+    fn crc8(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0u8, |crc, byte| (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 }))
+    }
+
+    fn crc16(bytes: &[u8]) -> u16 {
+        bytes.iter().fold(0u16, |crc, byte| (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 }))
+    }
+
+    // a stereo 16-bit frame with verbatim subframes, the left channel holds frame * 256 and the right one its negative
+    fn verbatim_frame(frame_number: u8) -> Vec<u8> {
+        // 8-bit block size follows the header, sample rate from streaminfo, independent stereo, 16 bits
+        let mut bytes = vec![0xFF, 0xF8, 0x60, 0x18, frame_number, (BLOCK_SIZE - 1) as u8];
+        bytes.push(crc8(&bytes));
+
+        for sign in [1i32, -1] {
+            bytes.push(0x02);
+            for frame in 0..BLOCK_SIZE {
+                let sample = sign * (frame_number as usize * BLOCK_SIZE + frame) as i32 * 256;
+                bytes.extend((sample as i16).to_be_bytes());
+            }
+        }
+
+        bytes.extend(crc16(&bytes).to_be_bytes());
+        bytes
+    }
+
+    fn seek_point(sample: u64, offset: u64) -> Vec<u8> {
+        [sample.to_be_bytes().as_slice(), &offset.to_be_bytes(), &(BLOCK_SIZE as u16).to_be_bytes()].concat()
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut bytes = 6u32.to_le_bytes().to_vec();
+        bytes.extend(b"vendor");
+        bytes.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            bytes.extend((comment.len() as u32).to_le_bytes());
+            bytes.extend(comment.as_bytes());
+        }
+        bytes
+    }
+
+    fn picture(picture_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = picture_type.to_be_bytes().to_vec();
+        bytes.extend(10u32.to_be_bytes());
+        bytes.extend(b"image/jpeg");
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend([0u8; 16]);
+        bytes.extend((data.len() as u32).to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn write_flac(name: &str, blocks: &[Vec<u8>], frames: usize) -> PathBuf {
+        let mut bytes = b"fLaC".to_vec();
+        bytes.extend(blocks.concat());
+        for frame_number in 0..frames {
+            bytes.extend(verbatim_frame(frame_number as u8));
+        }
+
+        let path = std::env::temp_dir().join(format!("{}-{}.flac", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn open(path: &Path) -> Result<FlacDecoder, DecoderError> {
+        let result = FlacDecoder::open(path);
+        std::fs::remove_file(path).unwrap();
+        result
+    }
+
+    fn expected(frames: std::ops::Range<usize>) -> Vec<f32> {
+        frames.flat_map(|frame| [frame as f32 / 128.0, -(frame as f32) / 128.0]).collect()
+    }
+
+    #[test]
+    fn parses_metadata_blocks() {
+        assert_eq!(read_stream_info(&stream_info(96000, 6, 24, 123456789)), Some((96000, 6, 24, 123456789)));
+        assert_eq!(read_stream_info(&[0u8; 33]), None);
+
+        let seek_table = [seek_point(0, 0), seek_point(4096, 1234), seek_point(PLACEHOLDER_SEEK_POINT, 0)].concat();
+        let seek_points: Vec<(u64, u64)> = read_seek_table(&seek_table).iter().map(|point| (point.sample, point.offset)).collect();
+        assert_eq!(seek_points, [(0, 0), (4096, 1234)]);
+
+        assert_eq!(flac_channel_mask(2), 0x3);
+        assert_eq!(flac_channel_mask(6), 0x3F);
+        assert_eq!(flac_channel_mask(9), 0);
+    }
+
+    #[test]
+    fn decodes_and_seeks() {
+        let frame_size = verbatim_frame(0).len() as u64;
+        let path = write_flac("flac-decode", &[
+            metadata_block(BLOCK_STREAMINFO, false, &stream_info(44100, 2, 16, 48)),
+            metadata_block(BLOCK_SEEKTABLE, false, &[seek_point(0, 0), seek_point(16, frame_size)].concat()),
+            metadata_block(BLOCK_VORBIS_COMMENT, false, &vorbis_comment(&["title=Song", "ARTIST=Band", "TITLE=Ignored"])),
+            metadata_block(BLOCK_PICTURE, false, &picture(4, b"back")),
+            metadata_block(BLOCK_PICTURE, true, &picture(3, b"front"))
+        ], 3);
+        let mut decoder = open(&path).unwrap();
+
+        let info = decoder.info();
+        assert_eq!((info.sample_rate, info.channels, info.channel_mask, info.frames), (44100, 2, 0x3, 48));
+        assert_eq!(decoder.metadata().title.as_deref(), Some("Song"));
+        assert_eq!(decoder.metadata().artist.as_deref(), Some("Band"));
+        assert_eq!(decoder.metadata().picture.as_ref().map(|picture| picture.data.as_slice()), Some(b"front".as_slice()));
+
+        let mut samples = Vec::new();
+        while decoder.read_frames(&mut samples, 10).unwrap() > 0 {}
+        assert_eq!(samples, expected(0..48));
+
+        // the second seek point skips the first frame, the rest of the way is decoded and dropped
+        samples.clear();
+        decoder.seek(37).unwrap();
+        decoder.read_frames(&mut samples, 4).unwrap();
+        assert_eq!(samples, expected(37..41));
+    }
+
+    #[test]
+    fn rejects_malformed_streams() {
+        let path = std::env::temp_dir().join(format!("flac-marker-{}.flac", std::process::id()));
+        std::fs::write(&path, b"OggS\0\0\0\0").unwrap();
+        assert!(matches!(open(&path), Err(DecoderError::Flac(_))));
+
+        let path = write_flac("flac-no-streaminfo", &[metadata_block(BLOCK_VORBIS_COMMENT, true, &vorbis_comment(&[]))], 1);
+        assert!(matches!(open(&path), Err(DecoderError::Flac(_))));
+
+        let path = write_flac("flac-zero-rate", &[metadata_block(BLOCK_STREAMINFO, true, &stream_info(0, 2, 16, 16))], 1);
+        assert!(matches!(open(&path), Err(DecoderError::Flac(_))));
+
+        // a metadata block running past the end of the file
+        let mut block = metadata_block(BLOCK_STREAMINFO, true, &stream_info(44100, 2, 16, 16));
+        block[1] = 0x10;
+        let path = write_flac("flac-truncated", &[block], 0);
+        assert!(matches!(open(&path), Err(DecoderError::Io(_))));
+    }
+}
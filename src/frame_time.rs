@@ -0,0 +1,91 @@
+use std::fmt::{Display, Formatter};
+
+// A position or length in sample frames of a track. Everything that shows or compares time goes through this,
+// so displays, the progress bar and seeking all agree with what the decoder actually played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTime {
+    pub frames: u64,
+    pub sample_rate: u32
+}
+
+impl FrameTime {
+    pub fn new(frames: u64, sample_rate: u32) -> Self {
+        FrameTime {
+            frames,
+            sample_rate
+        }
+    }
+
+    // the same rate at another frame
+    pub fn at(&self, frames: u64) -> Self {
+        FrameTime::new(frames, self.sample_rate)
+    }
+
+    pub fn seconds(&self) -> f64 {
+        if self.sample_rate == 0 {
+            return 0.0;
+        }
+
+        self.frames as f64 / self.sample_rate as f64
+    }
+
+    pub fn whole_seconds(&self) -> u64 {
+        self.frames.checked_div(self.sample_rate as u64).unwrap_or(0)
+    }
+
+    // how far along `duration` this position is, 0.0..=1.0
+    pub fn fraction_of(&self, duration: FrameTime) -> f64 {
+        if duration.frames == 0 {
+            return 0.0;
+        }
+
+        (self.frames as f64 / duration.frames as f64).min(1.0)
+    }
+}
+
+// mm:ss, or h:mm:ss past the hour. Seconds are truncated, so a position only reaches the duration at the very end
+impl Display for FrameTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.whole_seconds();
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+        if hours > 0 {
+            write!(f, "{}:{:02}:{:02}", hours, minutes, seconds)
+        } else {
+            write!(f, "{:02}:{:02}", minutes, seconds)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameTime;
+
+    #[test]
+    fn converts_frames_to_time() {
+        let time = FrameTime::new(66150, 44100);
+        assert_eq!(time.seconds(), 1.5);
+        assert_eq!(time.whole_seconds(), 1);
+        assert_eq!(time.at(88200), FrameTime::new(88200, 44100));
+        assert_eq!(time.fraction_of(FrameTime::new(132300, 44100)), 0.5);
+
+        assert_eq!(FrameTime::new(0, 48000).to_string(), "00:00");
+        // seconds are truncated, a frame short of a minute still shows 59 seconds
+        assert_eq!(FrameTime::new(60 * 48000 - 1, 48000).to_string(), "00:59");
+        assert_eq!(FrameTime::new(754 * 48000, 48000).to_string(), "12:34");
+        assert_eq!(FrameTime::new(3723 * 96000 + 95999, 96000).to_string(), "1:02:03");
+    }
+
+    #[test]
+    fn handles_empty_tracks() {
+        // no rate yet, as before a track is loaded
+        let time = FrameTime::new(1000, 0);
+        assert_eq!((time.seconds(), time.whole_seconds()), (0.0, 0));
+        assert_eq!(time.to_string(), "00:00");
+
+        let position = FrameTime::new(1000, 44100);
+        assert_eq!(position.fraction_of(FrameTime::new(0, 44100)), 0.0);
+        // decoders can run past the duration a header promised
+        assert_eq!(position.fraction_of(FrameTime::new(500, 44100)), 1.0);
+    }
+}
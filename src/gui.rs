@@ -1,47 +1,59 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::sync::{Arc};
 
-use crossbeam_queue::SegQueue;
+use crossbeam_queue::ArrayQueue;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use rand::{Rng, thread_rng};
+use rand::thread_rng;
 use rand::prelude::SliceRandom;
 
 use crate::{GuiToPlayerCommands, PlayerToGuiCommands, Playlist, Terminal};
 use crate::app::{AppEvent};
-use crate::playback_duration::PlaybackDuration;
+use crate::bwf::Timecode;
+use crate::decoder::{self, DecoderError};
+use crate::frame_time::FrameTime;
 use crate::playlist::Song;
 use crate::progress_bar::ProgressBar;
+use crate::resampler::{ResamplerKernels, ResamplerQuality};
+use crate::stream::DecoderStream;
 
 pub struct Gui {
-    to_gui_queue: Arc<SegQueue<PlayerToGuiCommands>>,
-    from_gui_queue: Arc<SegQueue<GuiToPlayerCommands>>,
+    to_gui_queue: Arc<ArrayQueue<PlayerToGuiCommands>>,
+    from_gui_queue: Arc<ArrayQueue<GuiToPlayerCommands>>,
     playlist: Playlist,
     playlist_index: usize,
     terminal: Terminal,
     prev_index: Option<usize>,
     shuffle: bool,
     playing: bool,
-    playback_duration: PlaybackDuration,
+    position: FrameTime,
     progress_bar: ProgressBar,
-    active_song: Option<Song>
+    active_song: Option<Song>,
+    show_track_info: bool,
+    loop_mode: bool,
+    resampler_quality: ResamplerQuality,
+    resampler_kernels: ResamplerKernels,
+    // the rate of the last track sent to the player, quality changes need a kernel for it
+    stream_sample_rate: Option<u32>
 }
 
 impl Gui {
-    pub fn new(from_gui_queue: Arc<SegQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<SegQueue<PlayerToGuiCommands>>) -> Self {
+    pub fn new(from_gui_queue: Arc<ArrayQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<ArrayQueue<PlayerToGuiCommands>>, device_sample_rate: u32) -> Self {
         Gui {
             to_gui_queue,
             from_gui_queue,
             playlist: Playlist::new(),
             playlist_index: 0,
             terminal: Terminal::new(),
-            playback_duration: PlaybackDuration::new(),
+            position: FrameTime::new(0, 0),
             progress_bar: ProgressBar::new(),
             shuffle: false,
             prev_index: None,
             playing: false,
-            active_song: None
+            active_song: None,
+            show_track_info: false,
+            loop_mode: false,
+            resampler_quality: ResamplerQuality::default(),
+            resampler_kernels: ResamplerKernels::new(device_sample_rate),
+            stream_sample_rate: None
         }
     }
 
@@ -55,9 +67,9 @@ impl Gui {
                     self.playing = true;
 
                     let song = self.get_song(self.playlist_index);
-                    let active_song  = Song::from_path(song.path.clone());
-
-                    self.active_song = Some(active_song);
+                    let active_song = Song::from_path(song.path.clone()).ok();
+                    self.position = song.duration.at(0);
+                    self.active_song = active_song;
                 },
                 PlayerToGuiCommands::Playing => {
                     self.playing = true;
@@ -65,10 +77,10 @@ impl Gui {
                 PlayerToGuiCommands::Paused => {
                     self.playing = false;
                 }
-                PlayerToGuiCommands::UpdateDuration {
-                    duration
+                PlayerToGuiCommands::UpdatePosition {
+                    frame
                 } => {
-                    self.playback_duration.advance(duration)
+                    self.position = self.position.at(frame);
                 }
             }
         }
@@ -85,6 +97,13 @@ impl Gui {
             }
         }
 
+        for skipped_song in &self.playlist.skipped {
+            self.terminal.cursor_row += 1;
+            self.terminal.cursor_col = 1;
+            self.terminal.set_cursor();
+            self.terminal.write(format!("Skipped {}", skipped_song));
+        }
+
         if let Some(active_song) = &self.active_song {
             self.terminal.cursor_row += 2;
             self.terminal.cursor_col = 1;
@@ -92,10 +111,59 @@ impl Gui {
             self.terminal.clear_line();
             self.terminal.write(active_song);
 
+            if let Some(picture) = &active_song.metadata.picture {
+                self.terminal.cursor_row += 1;
+                self.terminal.set_cursor();
+                self.terminal.clear_line();
+                self.terminal.write(picture);
+            }
+
             self.terminal.cursor_row += 1;
             self.terminal.set_cursor();
             self.terminal.clear_line();
-            self.progress_bar.update(&self.playback_duration, active_song.wav.duration, &mut self.terminal);
+            let marker_positions: Vec<u64> = active_song.info.markers
+                .iter()
+                .map(|marker| marker.position)
+                .collect();
+            self.progress_bar.update(self.position, active_song.duration, &marker_positions, &mut self.terminal);
+
+            let current_frame = self.position.frames;
+            let current_marker = active_song.info.markers
+                .iter()
+                .rev()
+                .find(|marker| marker.position <= current_frame);
+
+            if let Some(marker) = current_marker {
+                self.terminal.cursor_row += 1;
+                self.terminal.cursor_col = 1;
+                self.terminal.set_cursor();
+                self.terminal.clear_line();
+                match &marker.name {
+                    Some(name) => self.terminal.write(format!("Marker: {}", name)),
+                    None => self.terminal.write(format!("Marker: {}", marker.id))
+                }
+            }
+
+            if let Some(time_reference) = active_song.info.time_reference {
+                let timecode = Timecode::from_samples(time_reference + self.position.frames, self.position.sample_rate, active_song.info.timecode_rate);
+
+                self.terminal.cursor_row += 1;
+                self.terminal.cursor_col = 1;
+                self.terminal.set_cursor();
+                self.terminal.clear_line();
+                self.terminal.write(format!("Timecode: {}", timecode));
+            }
+
+            if self.show_track_info {
+                self.terminal.cursor_row += 1;
+                for line in track_info(active_song) {
+                    self.terminal.cursor_row += 1;
+                    self.terminal.cursor_col = 1;
+                    self.terminal.set_cursor();
+                    self.terminal.clear_line();
+                    self.terminal.write(line);
+                }
+            }
         }
 
         self.terminal.cursor_row += 2;
@@ -107,6 +175,16 @@ impl Gui {
         self.terminal.set_cursor();
         self.terminal.clear_line();
         self.terminal.write(format!("Shuffle: {}", self.shuffle));
+
+        self.terminal.cursor_row += 1;
+        self.terminal.set_cursor();
+        self.terminal.clear_line();
+        self.terminal.write(format!("Loop: {}", self.loop_mode));
+
+        self.terminal.cursor_row += 1;
+        self.terminal.set_cursor();
+        self.terminal.clear_line();
+        self.terminal.write(format!("Resampler: {}", self.resampler_quality));
     }
 
     pub fn handle_key_event(&mut self, event: KeyEvent) -> Option<AppEvent> {
@@ -130,9 +208,9 @@ impl Gui {
                 ..
             } => {
                 if !self.playing {
-                    self.from_gui_queue.push(GuiToPlayerCommands::PlayResume);
+                    self.send(GuiToPlayerCommands::PlayResume);
                 } else {
-                    self.from_gui_queue.push(GuiToPlayerCommands::Pause);
+                    self.send(GuiToPlayerCommands::Pause);
                 }
 
                 Some(AppEvent::Continue)
@@ -145,6 +223,55 @@ impl Gui {
                 self.shuffle();
                 Some(AppEvent::Continue)
             }
+            KeyEvent {
+                code: KeyCode::Char('i'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                self.show_track_info = !self.show_track_info;
+                Some(AppEvent::Continue)
+            }
+            KeyEvent {
+                code: KeyCode::Char('l'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                self.loop_mode = !self.loop_mode;
+                self.send(GuiToPlayerCommands::LoopMode {
+                    enabled: self.loop_mode
+                });
+                Some(AppEvent::Continue)
+            }
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                self.resampler_quality = self.resampler_quality.next();
+                if let Some(sample_rate) = self.stream_sample_rate {
+                    let kernel = self.resampler_kernels.get(sample_rate, self.resampler_quality);
+                    self.send(GuiToPlayerCommands::ResamplerQuality {
+                        kernel
+                    });
+                }
+                Some(AppEvent::Continue)
+            }
+            KeyEvent {
+                code: KeyCode::Char(']'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                self.next_marker();
+                Some(AppEvent::Continue)
+            }
+            KeyEvent {
+                code: KeyCode::Char('['),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                self.prev_marker();
+                Some(AppEvent::Continue)
+            }
             KeyEvent {
                 code: KeyCode::Right,
                 modifiers: KeyModifiers::NONE,
@@ -166,7 +293,7 @@ impl Gui {
                 modifiers: KeyModifiers::NONE,
                 ..
             } => {
-                self.from_gui_queue.push(GuiToPlayerCommands::Forward);
+                self.send(GuiToPlayerCommands::Forward);
                 Some(AppEvent::Continue)
             }
             KeyEvent {
@@ -174,7 +301,7 @@ impl Gui {
                 modifiers: KeyModifiers::NONE,
                 ..
             } => {
-                self.from_gui_queue.push(GuiToPlayerCommands::Rewind);
+                self.send(GuiToPlayerCommands::Rewind);
                 Some(AppEvent::Continue)
             }
             _ => Some(AppEvent::Continue)
@@ -192,12 +319,63 @@ impl Gui {
     }
 
     fn play_song(&mut self, index: usize) {
-        let buffer = self.load_buffer(index);
-        self.from_gui_queue.push(GuiToPlayerCommands::Play {
-            buffer
+        // the file may have changed since the playlist was scanned
+        let Ok(stream) = self.open_stream(index) else {
+            return;
+        };
+
+        let sample_rate = self.get_song(index).info.sample_rate;
+        let resampler_kernel = self.resampler_kernels.get(sample_rate, self.resampler_quality);
+        self.stream_sample_rate = Some(sample_rate);
+
+        let info = &self.get_song(index).info;
+        self.send(GuiToPlayerCommands::Play {
+            stream,
+            sample_rate,
+            channels: info.channels,
+            channel_mask: info.channel_mask,
+            sample_loop: info.loops.first().copied(),
+            resampler_kernel
         });
     }
 
+    // a full queue drops the command, the player drains it on every callback so that only happens when audio stalls
+    fn send(&self, command: GuiToPlayerCommands) {
+        let _ = self.from_gui_queue.push(command);
+    }
+
+    fn next_marker(&mut self) {
+        if let Some(song) = &self.active_song {
+            let current_frame = self.position.frames;
+            let next_marker = song.info.markers
+                .iter()
+                .find(|marker| marker.position > current_frame);
+
+            if let Some(marker) = next_marker {
+                self.send(GuiToPlayerCommands::Seek {
+                    frame: marker.position as usize
+                });
+            }
+        }
+    }
+
+    // like a CD player, going back within a second of a marker skips to the one before it
+    fn prev_marker(&mut self) {
+        if let Some(song) = &self.active_song {
+            let current_frame = self.position.frames;
+            let grace_frames = song.info.sample_rate as u64;
+            let prev_marker = song.info.markers
+                .iter()
+                .rev()
+                .find(|marker| marker.position + grace_frames < current_frame);
+
+            let frame = prev_marker.map(|marker| marker.position).unwrap_or(0);
+            self.send(GuiToPlayerCommands::Seek {
+                frame: frame as usize
+            });
+        }
+    }
+
     pub fn next_index(&mut self) -> usize {
         self.prev_index = Some(self.playlist_index);
         if self.playlist_index + 1 > self.playlist.indexes.len() - 1 {
@@ -219,19 +397,11 @@ impl Gui {
         self.playlist_index
     }
 
-    pub fn load_buffer(&self, playlist_index: usize) -> Vec<u8> {
+    pub fn open_stream(&self, playlist_index: usize) -> Result<DecoderStream, DecoderError> {
         let song = self.get_song(playlist_index);
-        let file = File::open(&song.path).unwrap();
-
-        let mut reader = BufReader::new(file);
-
-        // set seek position after the RIFF header
-        reader.seek(SeekFrom::Start(44)).unwrap();
+        let decoder = decoder::open(&song.path)?;
 
-        let mut buffer = vec![0u8; song.wav.header.data.chunk_size as usize];
-        reader.read_exact(&mut *buffer).unwrap();
-
-        buffer
+        Ok(DecoderStream::new(decoder))
     }
 
     fn get_song(&self, playlist_index: usize) -> &Song {
@@ -250,4 +420,44 @@ impl Gui {
             self.playlist.indexes.shuffle(&mut thread_rng());
         }
     }
-}
\ No newline at end of file
+}
+
+fn track_info(song: &Song) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let info = &song.info;
+    let metadata = &song.metadata;
+
+    let mut push = |label: &str, value: Option<&String>| {
+        if let Some(value) = value.filter(|value| !value.trim().is_empty()) {
+            lines.push(format!("{}: {}", label, value));
+        }
+    };
+
+    push("Album", metadata.album.as_ref());
+    push("Date", metadata.date.as_ref());
+    push("Genre", metadata.genre.as_ref());
+    push("Track", metadata.track.as_ref());
+    push("Comment", metadata.comment.as_ref());
+
+    for (label, value) in &info.details {
+        push(label, Some(value));
+    }
+
+    for marker in &info.markers {
+        let position = song.duration.at(marker.position);
+        let name = marker.name.clone().unwrap_or_else(|| format!("#{}", marker.id));
+        push("Marker", Some(&format!("{} at {:.2}s", name, position.seconds())));
+    }
+
+    for sample_loop in &info.loops {
+        let start = song.duration.at(sample_loop.start);
+        let end = song.duration.at(sample_loop.end);
+        let play_count = match sample_loop.play_count {
+            0 => String::from("infinite"),
+            count => format!("{}x", count)
+        };
+        push("Loop", Some(&format!("{:.2}s - {:.2}s, {}", start.seconds(), end.seconds(), play_count)));
+    }
+
+    lines
+}
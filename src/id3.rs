@@ -0,0 +1,496 @@
+use crate::metadata::{Metadata, Picture};
+
+const ID3V1_GENRES: [&str; 192] = [
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop", "Jazz", "Metal",
+    "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock", "Techno", "Industrial",
+    "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack", "Euro-Techno", "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk",
+    "Fusion", "Trance", "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise",
+    "AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop", "Instrumental Rock", "Ethnic", "Gothic",
+    "Darkwave", "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream", "Southern Rock", "Comedy", "Cult", "Gangsta",
+    "Top 40", "Christian Rap", "Pop/Funk", "Jungle", "Native American", "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes",
+    "Trailer", "Lo-Fi", "Tribal", "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+    // Winamp extensions
+    "Folk", "Folk-Rock", "National Folk", "Swing", "Fast Fusion", "Bebop", "Latin", "Revival", "Celtic", "Bluegrass",
+    "Avantgarde", "Gothic Rock", "Progressive Rock", "Psychedelic Rock", "Symphonic Rock", "Slow Rock", "Big Band", "Chorus", "Easy Listening", "Acoustic",
+    "Humour", "Speech", "Chanson", "Opera", "Chamber Music", "Sonata", "Symphony", "Booty Bass", "Primus", "Porn Groove",
+    "Satire", "Slow Jam", "Club", "Tango", "Samba", "Folklore", "Ballad", "Power Ballad", "Rhythmic Soul", "Freestyle",
+    "Duet", "Punk Rock", "Drum Solo", "A Cappella", "Euro-House", "Dance Hall", "Goa", "Drum & Bass", "Club-House", "Hardcore Techno",
+    "Terror", "Indie", "BritPop", "Afro-Punk", "Polsk Punk", "Beat", "Christian Gangsta Rap", "Heavy Metal", "Black Metal", "Crossover",
+    "Contemporary Christian", "Christian Rock", "Merengue", "Salsa", "Thrash Metal", "Anime", "Jpop", "Synthpop", "Abstract", "Art Rock",
+    "Baroque", "Bhangra", "Big Beat", "Breakbeat", "Chillout", "Downtempo", "Dub", "EBM", "Eclectic", "Electro",
+    "Electroclash", "Emo", "Experimental", "Garage", "Global", "IDM", "Illbient", "Industro-Goth", "Jam Band", "Krautrock",
+    "Leftfield", "Lounge", "Math Rock", "New Romantic", "Nu-Breakz", "Post-Punk", "Post-Rock", "Psytrance", "Shoegaze", "Space Rock",
+    "Trop Rock", "World Music", "Neoclassical", "Audiobook", "Audio Theatre", "Neue Deutsche Welle", "Podcast", "Indie Rock", "G-Funk", "Dubstep",
+    "Garage Rock", "Psybient"
+];
+
+// Reads the frames of an ID3v2.2, ID3v2.3 or ID3v2.4 tag, `tag_bytes` starts at the "ID3" identifier
+pub fn read_id3v2(tag_bytes: &[u8]) -> Option<Metadata> {
+    if tag_bytes.len() < 10 || &tag_bytes[0..3] != b"ID3" {
+        return None;
+    }
+
+    let major_version = tag_bytes[3];
+    if !(2..=4).contains(&major_version) {
+        return None;
+    }
+
+    let flags = tag_bytes[5];
+    let tag_size = syncsafe_u32(&tag_bytes[6..10]) as usize;
+    let tag_end = (10 + tag_size).min(tag_bytes.len());
+
+    // v2.2 and v2.3 unsynchronise the whole tag, v2.4 flags it per frame
+    let body = if major_version < 4 && flags & 0x80 != 0 {
+        remove_unsynchronisation(&tag_bytes[10..tag_end])
+    } else {
+        tag_bytes[10..tag_end].to_vec()
+    };
+
+    if major_version == 2 {
+        return Some(read_id3v22_frames(&body));
+    }
+
+    let mut offset = 0;
+    if flags & 0x40 != 0 && body.len() >= 4 {
+        offset = if major_version == 4 {
+            syncsafe_u32(&body[0..4]) as usize
+        } else {
+            be_u32(&body[0..4]) as usize + 4
+        };
+    }
+
+    let mut metadata = Metadata::default();
+
+    while offset + 10 <= body.len() {
+        let frame_id = &body[offset..offset + 4];
+        if frame_id[0] == 0 {
+            // reached the padding
+            break;
+        }
+
+        let frame_size = if major_version == 4 {
+            syncsafe_u32(&body[offset + 4..offset + 8])
+        } else {
+            be_u32(&body[offset + 4..offset + 8])
+        } as usize;
+        let frame_flags = u16::from_be_bytes([body[offset + 8], body[offset + 9]]);
+
+        let frame_start = offset + 10;
+        let frame_end = (frame_start + frame_size).min(body.len());
+        offset = frame_start + frame_size;
+
+        if let Some(frame) = frame_data(&body[frame_start..frame_end], frame_flags, major_version) {
+            read_frame(frame_id, &frame, &mut metadata);
+        }
+    }
+
+    Some(metadata)
+}
+
+// v2.2 frames have three character ids and a six byte header without flags
+fn read_id3v22_frames(body: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut offset = 0;
+
+    while offset + 6 <= body.len() {
+        let frame_id = &body[offset..offset + 3];
+        if frame_id[0] == 0 {
+            break;
+        }
+
+        let frame_size = u32::from_be_bytes([0, body[offset + 3], body[offset + 4], body[offset + 5]]) as usize;
+        let frame_start = offset + 6;
+        let frame = &body[frame_start..(frame_start + frame_size).min(body.len())];
+        offset = frame_start + frame_size;
+
+        let frame_id: &[u8] = match frame_id {
+            b"TT2" => b"TIT2",
+            b"TP1" => b"TPE1",
+            b"TAL" => b"TALB",
+            b"TRK" => b"TRCK",
+            b"TYE" => b"TYER",
+            b"TCO" => b"TCON",
+            b"COM" => b"COMM",
+            b"PIC" => {
+                metadata.picture = id3v22_picture_frame(frame);
+                continue;
+            }
+            _ => continue
+        };
+        read_frame(frame_id, frame, &mut metadata);
+    }
+
+    metadata
+}
+
+// Reads the 128 byte ID3v1 tag found at the very end of older MP3 files, ID3v1.1 keeps the track number in the comment
+pub fn read_id3v1(tag_bytes: &[u8]) -> Option<Metadata> {
+    if tag_bytes.len() != 128 || &tag_bytes[0..3] != b"TAG" {
+        return None;
+    }
+
+    let has_track = tag_bytes[125] == 0 && tag_bytes[126] != 0;
+    let comment_end = if has_track { 125 } else { 127 };
+
+    Some(Metadata {
+        title: id3v1_text(&tag_bytes[3..33]),
+        artist: id3v1_text(&tag_bytes[33..63]),
+        album: id3v1_text(&tag_bytes[63..93]),
+        date: id3v1_text(&tag_bytes[93..97]),
+        genre: ID3V1_GENRES.get(tag_bytes[127] as usize).map(|genre| String::from(*genre)),
+        track: if has_track { Some(tag_bytes[126].to_string()) } else { None },
+        comment: id3v1_text(&tag_bytes[97..comment_end]),
+        picture: None
+    })
+}
+
+// strips the per frame extras ID3 allows, compressed and encrypted frames are skipped
+fn frame_data(frame: &[u8], frame_flags: u16, major_version: u8) -> Option<Vec<u8>> {
+    if major_version == 4 {
+        if frame_flags & 0x000C != 0 {
+            return None;
+        }
+
+        // a group id byte comes first, then the data length indicator
+        let frame = if frame_flags & 0x0040 != 0 && !frame.is_empty() {
+            &frame[1..]
+        } else {
+            frame
+        };
+        let frame = if frame_flags & 0x0001 != 0 && frame.len() >= 4 {
+            &frame[4..]
+        } else {
+            frame
+        };
+
+        if frame_flags & 0x0002 != 0 {
+            return Some(remove_unsynchronisation(frame));
+        }
+
+        return Some(frame.to_vec());
+    }
+
+    if frame_flags & 0x00C0 != 0 {
+        return None;
+    }
+
+    if frame_flags & 0x0020 != 0 && !frame.is_empty() {
+        return Some(frame[1..].to_vec());
+    }
+
+    Some(frame.to_vec())
+}
+
+fn read_frame(frame_id: &[u8], frame: &[u8], metadata: &mut Metadata) {
+    match frame_id {
+        b"TIT2" => metadata.title = text_frame(frame),
+        b"TPE1" => metadata.artist = text_frame(frame),
+        b"TALB" => metadata.album = text_frame(frame),
+        b"TRCK" => metadata.track = text_frame(frame),
+        b"TYER" | b"TDRC" => metadata.date = text_frame(frame),
+        b"TCON" => metadata.genre = text_frame(frame).map(|genre| genre_name(&genre)),
+        b"COMM" => metadata.comment = comment_frame(frame),
+        b"APIC" => metadata.picture = picture_frame(frame),
+        _ => {}
+    }
+}
+
+fn text_frame(frame: &[u8]) -> Option<String> {
+    let (encoding, text) = frame.split_first()?;
+    let text = decode_text(*encoding, text);
+
+    // v2.4 separates multiple values with NULs, only the first one is kept
+    let text = text.split('\0').next().unwrap_or_default().trim().to_string();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+fn comment_frame(frame: &[u8]) -> Option<String> {
+    if frame.len() < 4 {
+        return None;
+    }
+
+    let encoding = frame[0];
+    let (_, text) = split_terminated(encoding, &frame[4..]);
+    let text = decode_text(encoding, text).trim_end_matches('\0').trim().to_string();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+fn picture_frame(frame: &[u8]) -> Option<Picture> {
+    let (encoding, rest) = frame.split_first()?;
+
+    let mime_end = rest.iter().position(|byte| *byte == 0)?;
+    let mime_type = String::from_utf8_lossy(&rest[..mime_end]).to_string();
+
+    let picture_type = *rest.get(mime_end + 1)?;
+    let (description, data) = split_terminated(*encoding, rest.get(mime_end + 2..)?);
+
+    Some(Picture {
+        mime_type,
+        picture_type,
+        description: decode_text(*encoding, description),
+        data: data.to_vec()
+    })
+}
+
+// v2.2 names the image format with three characters instead of a mime type
+fn id3v22_picture_frame(frame: &[u8]) -> Option<Picture> {
+    let encoding = *frame.first()?;
+    let format = String::from_utf8_lossy(frame.get(1..4)?).to_ascii_lowercase();
+    let picture_type = *frame.get(4)?;
+    let (description, data) = split_terminated(encoding, frame.get(5..)?);
+
+    Some(Picture {
+        mime_type: match format.as_str() {
+            "jpg" => String::from("image/jpeg"),
+            "png" => String::from("image/png"),
+            _ => format!("image/{}", format)
+        },
+        picture_type,
+        description: decode_text(encoding, description),
+        data: data.to_vec()
+    })
+}
+
+// ID3v1 fields are fixed width latin-1, padded with NULs or spaces
+fn id3v1_text(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    let text: String = bytes[..end].iter().map(|byte| *byte as char).collect();
+    let text = text.trim().to_string();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+// TCON holds either a plain genre name or ID3v1 references like "(17)" or "17"
+fn genre_name(genre: &str) -> String {
+    let reference = genre.trim_start_matches('(').split(')').next().unwrap_or_default();
+
+    match reference.parse::<usize>() {
+        Ok(index) if index < ID3V1_GENRES.len() => String::from(ID3V1_GENRES[index]),
+        _ => String::from(genre)
+    }
+}
+
+// splits off a NUL terminated string, UTF-16 encodings terminate with two NUL bytes
+fn split_terminated(encoding: u8, bytes: &[u8]) -> (&[u8], &[u8]) {
+    if encoding == 1 || encoding == 2 {
+        let mut index = 0;
+        while index + 1 < bytes.len() {
+            if bytes[index] == 0 && bytes[index + 1] == 0 {
+                return (&bytes[..index], &bytes[index + 2..]);
+            }
+            index += 2;
+        }
+        return (bytes, &[]);
+    }
+
+    match bytes.iter().position(|byte| *byte == 0) {
+        Some(index) => (&bytes[..index], &bytes[index + 1..]),
+        None => (bytes, &[])
+    }
+}
+
+fn decode_text(encoding: u8, bytes: &[u8]) -> String {
+    match encoding {
+        0 => bytes.iter().map(|byte| *byte as char).collect(),
+        1 => {
+            // UTF-16 with byte order mark
+            if bytes.len() >= 2 && bytes[0] == 0xFF && bytes[1] == 0xFE {
+                decode_utf16(&bytes[2..], false)
+            } else if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
+                decode_utf16(&bytes[2..], true)
+            } else {
+                decode_utf16(bytes, false)
+            }
+        }
+        2 => decode_utf16(bytes, true),
+        _ => String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| if big_endian {
+            u16::from_be_bytes([unit[0], unit[1]])
+        } else {
+            u16::from_le_bytes([unit[0], unit[1]])
+        })
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+fn remove_unsynchronisation(bytes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut previous = 0u8;
+
+    for byte in bytes {
+        if previous == 0xFF && *byte == 0x00 {
+            previous = 0x00;
+            continue;
+        }
+        output.push(*byte);
+        previous = *byte;
+    }
+
+    output
+}
+
+fn syncsafe_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, byte| (value << 7) | (*byte & 0x7F) as u32)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_id3v1, read_id3v2, ID3V1_GENRES};
+
+    fn tag(major_version: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let body = frames.concat();
+        let size = body.len() as u32;
+
+        let mut bytes = vec![b'I', b'D', b'3', major_version, 0, 0];
+        bytes.extend([(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F]);
+        bytes.extend(body);
+        bytes
+    }
+
+    // v2.3 sizes are plain big-endian, v2.4 ones syncsafe
+    fn frame(major_version: u8, id: &[u8; 4], flags: u16, data: &[u8]) -> Vec<u8> {
+        let size = data.len() as u32;
+        let mut bytes = id.to_vec();
+        if major_version == 4 {
+            bytes.extend([(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F]);
+        } else {
+            bytes.extend(size.to_be_bytes());
+        }
+        bytes.extend(flags.to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_v23_text_frames() {
+        let artist: Vec<u8> = [1, 0xFF, 0xFE].into_iter().chain("Bänd".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let metadata = read_id3v2(&tag(3, &[
+            frame(3, b"TIT2", 0, b"\0Song"),
+            frame(3, b"TPE1", 0, &artist),
+            frame(3, b"TCON", 0, b"\0(17)"),
+            frame(3, b"COMM", 0, b"\0engShort\0Long comment"),
+            vec![0; 16]
+        ])).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Bänd"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.comment.as_deref(), Some("Long comment"));
+    }
+
+    #[test]
+    fn reads_v24_frames_with_flags() {
+        // a 200 byte frame needs the second syncsafe byte
+        let album: Vec<u8> = [3].into_iter().chain(std::iter::repeat_n(b'a', 199)).collect();
+        let metadata = read_id3v2(&tag(4, &[
+            frame(4, b"TALB", 0, &album),
+            // group id byte, then the four byte data length indicator
+            frame(4, b"TIT2", 0x0041, &[0x01, 0, 0, 0, 5, 3, b'S', b'o', b'n', b'g']),
+            frame(4, b"TCON", 0, b"\x03150"),
+            frame(4, b"TPE1", 0, b"\x03One\0Two")
+        ])).unwrap();
+
+        assert_eq!(metadata.album.map(|album| album.len()), Some(199));
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.genre.as_deref(), Some("Baroque"));
+        assert_eq!(metadata.artist.as_deref(), Some("One"));
+    }
+
+    #[test]
+    fn reads_v22_frames() {
+        let mut frames = Vec::new();
+        for (id, data) in [(b"TT2", &b"\0Song"[..]), (b"TCO", b"\0(80)"), (b"PIC", b"\0PNG\x03Cover\0\x89PNG"), (b"XXX", b"\0skipped")] {
+            frames.extend(id);
+            frames.extend(&(data.len() as u32).to_be_bytes()[1..]);
+            frames.extend(data);
+        }
+        let metadata = read_id3v2(&tag(2, &[frames])).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.genre.as_deref(), Some("Folk"));
+        let picture = metadata.picture.unwrap();
+        assert_eq!((picture.mime_type.as_str(), picture.picture_type, picture.description.as_str()), ("image/png", 3, "Cover"));
+        assert_eq!(picture.data, b"\x89PNG");
+    }
+
+    #[test]
+    fn reads_id3v1() {
+        let mut bytes = vec![0u8; 128];
+        bytes[0..3].copy_from_slice(b"TAG");
+        bytes[3..7].copy_from_slice(b"Song");
+        bytes[33..39].copy_from_slice(b"Artist");
+        bytes[93..97].copy_from_slice(b"1999");
+        bytes[97..104].copy_from_slice(b"Comment");
+        bytes[127] = 17;
+
+        let metadata = read_id3v1(&bytes).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.album, None);
+        assert_eq!(metadata.date.as_deref(), Some("1999"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.track, None);
+
+        // ID3v1.1 keeps the track in the last comment byte behind a NUL
+        bytes[126] = 7;
+        bytes[127] = 255;
+        let metadata = read_id3v1(&bytes).unwrap();
+        assert_eq!(metadata.track.as_deref(), Some("7"));
+        assert_eq!(metadata.comment.as_deref(), Some("Comment"));
+        assert_eq!(metadata.genre, None);
+
+        assert!(read_id3v1(&bytes[..127]).is_none());
+        bytes[0] = b'X';
+        assert!(read_id3v1(&bytes).is_none());
+    }
+
+    #[test]
+    fn genre_table_has_winamp_extensions() {
+        assert_eq!(ID3V1_GENRES.len(), 192);
+        assert_eq!(ID3V1_GENRES[79], "Hard Rock");
+        assert_eq!(ID3V1_GENRES[80], "Folk");
+        assert_eq!(ID3V1_GENRES[191], "Psybient");
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        assert!(read_id3v2(b"ID3").is_none());
+        assert!(read_id3v2(b"TAG\x03\0\0\0\0\0\0").is_none());
+        assert!(read_id3v2(&tag(5, &[frame(4, b"TIT2", 0, b"\0Song")])).is_none());
+
+        // sizes past the end of the tag keep what's there
+        let mut bytes = tag(3, &[frame(3, b"TIT2", 0, b"\0Song")]);
+        bytes[17] = 0x7F;
+        assert_eq!(read_id3v2(&bytes).unwrap().title.as_deref(), Some("Song"));
+
+        // an extended header larger than the tag leaves no frames
+        let mut bytes = tag(3, &[vec![0, 0, 0xFF, 0xFF], frame(3, b"TIT2", 0, b"\0Song")]);
+        bytes[5] = 0x40;
+        assert_eq!(read_id3v2(&bytes).unwrap().title, None);
+    }
+}
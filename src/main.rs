@@ -1,15 +1,18 @@
 use std::sync::{Arc};
 
-use crossbeam_queue::SegQueue;
+use crossbeam_queue::ArrayQueue;
 
 use crate::app::App;
 use crate::output::Output;
 use crate::player::Player;
 use crate::playlist::Playlist;
+use crate::resampler::ResamplerKernel;
+use crate::markers::SampleLoop;
+use crate::stream::DecoderStream;
 use crate::terminal::Terminal;
 
 mod player;
-mod playback_duration;
+mod frame_time;
 mod terminal;
 mod progress_bar;
 mod playlist;
@@ -17,15 +20,52 @@ mod gui;
 mod output;
 mod app;
 mod wav;
+mod wav_writer;
+mod aiff;
+mod sample_format;
+mod resampler;
+mod channel_map;
+mod dither;
+mod codec;
+mod adpcm;
+mod metadata;
+mod id3;
+mod bwf;
+mod markers;
+mod stream;
+mod decoder;
+mod flac;
+mod mp3;
+mod ogg;
+mod vorbis;
+mod opus;
+
+// both queues are allocated up front, the audio callback must never grow them
+const QUEUE_CAPACITY: usize = 256;
 
 pub enum GuiToPlayerCommands {
     Play {
-        buffer: Vec<u8>
+        stream: DecoderStream,
+        sample_rate: u32,
+        channels: u16,
+        channel_mask: u32,
+        sample_loop: Option<SampleLoop>,
+        resampler_kernel: Arc<ResamplerKernel>
     },
     PlayResume,
     Pause,
     Forward,
-    Rewind
+    Rewind,
+    Seek {
+        frame: usize
+    },
+    LoopMode {
+        enabled: bool
+    },
+    // a kernel of the new quality for the track that's playing
+    ResamplerQuality {
+        kernel: Arc<ResamplerKernel>
+    }
 }
 
 pub enum PlayerToGuiCommands {
@@ -33,15 +73,16 @@ pub enum PlayerToGuiCommands {
     Playing,
     Play,
     Paused,
-    UpdateDuration {
-        duration: u128
+    // the frame of the track that's playing, in the track's own sample rate
+    UpdatePosition {
+        frame: u64
     }
 }
 
 fn main() {
-    let from_gui_queue = Arc::new(SegQueue::new());
-    let to_gui_queue = Arc::new(SegQueue::new());
+    let from_gui_queue = Arc::new(ArrayQueue::new(QUEUE_CAPACITY));
+    let to_gui_queue = Arc::new(ArrayQueue::new(QUEUE_CAPACITY));
 
-    let _stream = Output::new(from_gui_queue.clone(), to_gui_queue.clone());
-    let _app = App::new(from_gui_queue.clone(), to_gui_queue.clone());
+    let (_stream, device_sample_rate) = Output::new(from_gui_queue.clone(), to_gui_queue.clone());
+    App::new(from_gui_queue.clone(), to_gui_queue.clone(), device_sample_rate);
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::wav::Endianness;
+
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub id: u32,
+    pub position: u64,
+    pub name: Option<String>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampleLoop {
+    pub start: u64,
+    pub end: u64,
+    pub play_count: u32
+}
+
+// cue points, positions are in sample frames from the start of the data chunk
+pub fn read_cue_points(cue_bytes: &[u8], endianness: Endianness) -> Vec<Marker> {
+    if cue_bytes.len() < 4 {
+        return Vec::new();
+    }
+
+    let count = endianness.u32(cue_bytes, 0) as usize;
+
+    cue_bytes[4..]
+        .chunks_exact(24)
+        .take(count)
+        .map(|point| Marker {
+            id: endianness.u32(point, 0),
+            position: endianness.u32(point, 20) as u64,
+            name: None
+        })
+        .collect()
+}
+
+// labl and note sub chunks of an adtl LIST, keyed by cue point id. `adtl_bytes` starts after the adtl type id
+pub fn read_labels(adtl_bytes: &[u8], endianness: Endianness) -> HashMap<u32, String> {
+    let mut labels = HashMap::new();
+    let mut offset = 0;
+
+    while offset + 12 <= adtl_bytes.len() {
+        let id = &adtl_bytes[offset..offset + 4];
+        let size = endianness.u32(adtl_bytes, offset + 4) as usize;
+        let body_start = offset + 8;
+        let body_end = (body_start + size).min(adtl_bytes.len());
+
+        if (id == b"labl" || id == b"note") && body_end >= body_start + 4 {
+            let cue_id = endianness.u32(adtl_bytes, body_start);
+            let text = &adtl_bytes[body_start + 4..body_end];
+            let text_end = text.iter().position(|byte| *byte == 0).unwrap_or(text.len());
+            let text = String::from_utf8_lossy(&text[..text_end]).trim().to_string();
+
+            // a label wins over a note for the same cue point
+            if !text.is_empty() && (id == b"labl" || !labels.contains_key(&cue_id)) {
+                labels.insert(cue_id, text);
+            }
+        }
+
+        offset = body_start + size + (size & 1);
+    }
+
+    labels
+}
+
+// loop regions of the sampler chunk, end is the last frame played before jumping back to start
+pub fn read_sample_loops(smpl_bytes: &[u8], endianness: Endianness) -> Vec<SampleLoop> {
+    if smpl_bytes.len() < 36 {
+        return Vec::new();
+    }
+
+    let count = endianness.u32(smpl_bytes, 28) as usize;
+
+    smpl_bytes[36..]
+        .chunks_exact(24)
+        .take(count)
+        .map(|sample_loop| SampleLoop {
+            start: endianness.u32(sample_loop, 8) as u64,
+            end: endianness.u32(sample_loop, 12) as u64,
+            play_count: endianness.u32(sample_loop, 20)
+        })
+        .filter(|sample_loop| sample_loop.start < sample_loop.end)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_cue_points, read_labels, read_sample_loops};
+    use crate::wav::Endianness;
+
+    fn cue_point(id: u32, position: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(id.to_le_bytes());
+        bytes.extend(position.to_le_bytes());
+        bytes.extend(b"data");
+        bytes.extend([0u8; 8]);
+        bytes.extend(position.to_le_bytes());
+        bytes
+    }
+
+    fn sub_chunk(id: &[u8; 4], cue_id: u32, text: &[u8]) -> Vec<u8> {
+        let size = 4 + text.len() as u32;
+        let mut bytes = id.to_vec();
+        bytes.extend(size.to_le_bytes());
+        bytes.extend(cue_id.to_le_bytes());
+        bytes.extend(text);
+        if size % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn smpl(loops: &[(u32, u32, u32)]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 36];
+        bytes[28..32].copy_from_slice(&(loops.len() as u32).to_le_bytes());
+        for (start, end, play_count) in loops {
+            bytes.extend([0u8; 8]);
+            bytes.extend(start.to_le_bytes());
+            bytes.extend(end.to_le_bytes());
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend(play_count.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_cue_points_and_labels() {
+        let cue_bytes = [2u32.to_le_bytes().to_vec(), cue_point(1, 4410), cue_point(2, 88200)].concat();
+        let positions: Vec<(u32, u64)> = read_cue_points(&cue_bytes, Endianness::Little).iter().map(|marker| (marker.id, marker.position)).collect();
+        assert_eq!(positions, [(1, 4410), (2, 88200)]);
+
+        let adtl_bytes = [
+            sub_chunk(b"note", 1, b"A note\0"),
+            sub_chunk(b"labl", 1, b"Verse\0"),
+            sub_chunk(b"note", 2, b"Chorus\0"),
+            sub_chunk(b"ltxt", 2, b"ignored")
+        ].concat();
+        let labels = read_labels(&adtl_bytes, Endianness::Little);
+        assert_eq!(labels.get(&1).map(String::as_str), Some("Verse"));
+        assert_eq!(labels.get(&2).map(String::as_str), Some("Chorus"));
+    }
+
+    #[test]
+    fn reads_sample_loops() {
+        let loops = read_sample_loops(&smpl(&[(100, 200, 0), (300, 400, 3)]), Endianness::Little);
+        let loops: Vec<(u64, u64, u32)> = loops.iter().map(|sample_loop| (sample_loop.start, sample_loop.end, sample_loop.play_count)).collect();
+
+        assert_eq!(loops, [(100, 200, 0), (300, 400, 3)]);
+    }
+
+    #[test]
+    fn skips_malformed_entries() {
+        // the count claims more cue points than there are bytes for
+        let cue_bytes = [5u32.to_le_bytes().to_vec(), cue_point(1, 10), vec![0u8; 12]].concat();
+        assert_eq!(read_cue_points(&cue_bytes, Endianness::Little).len(), 1);
+        assert!(read_cue_points(&[1, 0], Endianness::Little).is_empty());
+
+        // a label whose size runs past the end keeps what's there
+        let mut adtl_bytes = sub_chunk(b"labl", 1, b"Cut");
+        adtl_bytes[4] = 0xFF;
+        assert_eq!(read_labels(&adtl_bytes, Endianness::Little).get(&1).map(String::as_str), Some("Cut"));
+
+        // empty and backwards loops are dropped
+        assert!(read_sample_loops(&smpl(&[(200, 200, 0), (300, 100, 0)]), Endianness::Little).is_empty());
+        assert!(read_sample_loops(&[0u8; 20], Endianness::Little).is_empty());
+    }
+}
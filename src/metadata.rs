@@ -0,0 +1,370 @@
+use std::fmt::{Display, Formatter};
+
+use crate::wav::Endianness;
+
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    pub track: Option<String>,
+    pub comment: Option<String>,
+    pub picture: Option<Picture>
+}
+
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub mime_type: String,
+    pub picture_type: u8,
+    pub description: String,
+    pub data: Vec<u8>
+}
+
+impl Metadata {
+    // parses the sub chunks of a LIST chunk of type INFO, `info_bytes` starts right after the INFO type id
+    pub fn from_info_list(info_bytes: &[u8], endianness: Endianness) -> Self {
+        let mut metadata = Metadata::default();
+        let mut offset = 0;
+
+        while offset + 8 <= info_bytes.len() {
+            let id = &info_bytes[offset..offset + 4];
+            let size = endianness.u32(info_bytes, offset + 4) as usize;
+            let value_start = offset + 8;
+            let value_end = (value_start + size).min(info_bytes.len());
+
+            let value = info_text(&info_bytes[value_start..value_end]);
+
+            match id {
+                b"INAM" => metadata.title = value,
+                b"IART" => metadata.artist = value,
+                b"IPRD" => metadata.album = value,
+                b"ICRD" => metadata.date = value,
+                b"IGNR" => metadata.genre = value,
+                b"ITRK" | b"IPRT" => metadata.track = value,
+                b"ICMT" => metadata.comment = value,
+                _ => {}
+            }
+
+            // INFO values are word aligned like any other RIFF chunk
+            offset = value_start + size + (size & 1);
+        }
+
+        metadata
+    }
+
+    // parses a Vorbis comment block as used by FLAC and Ogg streams, without the Vorbis framing bit
+    pub fn from_vorbis_comment(comment_bytes: &[u8]) -> Self {
+        let mut metadata = Metadata::default();
+
+        let le_u32 = |at: usize| -> Option<usize> {
+            let bytes = comment_bytes.get(at..at + 4)?;
+            Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        };
+
+        let Some(vendor_length) = le_u32(0) else {
+            return metadata;
+        };
+        let Some(count) = le_u32(4 + vendor_length) else {
+            return metadata;
+        };
+
+        let mut offset = 8 + vendor_length;
+        for _ in 0..count {
+            let Some(length) = le_u32(offset) else {
+                break;
+            };
+            let Some(comment) = comment_bytes.get(offset + 4..offset + 4 + length) else {
+                break;
+            };
+            offset += 4 + length;
+
+            let comment = String::from_utf8_lossy(comment);
+            let Some((name, value)) = comment.split_once('=') else {
+                continue;
+            };
+
+            // Ogg streams keep cover art in a comment, as a base64 encoded FLAC PICTURE block
+            if name.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE") {
+                let picture = decode_base64(value).and_then(|bytes| Picture::from_flac_picture(&bytes));
+                let is_front_cover = picture.as_ref().is_some_and(|picture| picture.picture_type == 3);
+                if metadata.picture.is_none() || is_front_cover {
+                    metadata.picture = picture.or(metadata.picture);
+                }
+                continue;
+            }
+
+            // field names are case insensitive, the first of repeated fields wins
+            let value = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+            let field = match name.to_ascii_uppercase().as_str() {
+                "TITLE" => &mut metadata.title,
+                "ARTIST" => &mut metadata.artist,
+                "ALBUM" => &mut metadata.album,
+                "DATE" => &mut metadata.date,
+                "GENRE" => &mut metadata.genre,
+                "TRACKNUMBER" => &mut metadata.track,
+                "COMMENT" | "DESCRIPTION" => &mut metadata.comment,
+                _ => continue
+            };
+            if field.is_none() {
+                *field = value;
+            }
+        }
+
+        metadata
+    }
+
+    // fills the fields this tag is missing with the ones from `other`
+    pub fn or(self, other: Metadata) -> Self {
+        Metadata {
+            title: self.title.or(other.title),
+            artist: self.artist.or(other.artist),
+            album: self.album.or(other.album),
+            date: self.date.or(other.date),
+            genre: self.genre.or(other.genre),
+            track: self.track.or(other.track),
+            comment: self.comment.or(other.comment),
+            picture: self.picture.or(other.picture)
+        }
+    }
+
+    // "3/12" style track fields are reduced to the track number
+    pub fn track_number(&self) -> Option<u32> {
+        self.track.as_ref()?.split('/').next()?.trim().parse().ok()
+    }
+}
+
+impl Picture {
+    // the FLAC PICTURE block layout, Ogg streams carry the same bytes base64 encoded
+    pub fn from_flac_picture(picture_bytes: &[u8]) -> Option<Self> {
+        let be_u32 = |at: usize| -> Option<usize> {
+            let bytes = picture_bytes.get(at..at + 4)?;
+            Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        };
+
+        let picture_type = be_u32(0)? as u8;
+
+        let mime_length = be_u32(4)?;
+        let mime_type = String::from_utf8_lossy(picture_bytes.get(8..8 + mime_length)?).to_string();
+
+        let description_start = 8 + mime_length;
+        let description_length = be_u32(description_start)?;
+        let description = picture_bytes.get(description_start + 4..description_start + 4 + description_length)?;
+        let description = String::from_utf8_lossy(description).to_string();
+
+        // width, height, colour depth and palette size are skipped
+        let data_start = description_start + 4 + description_length + 16;
+        let data_length = be_u32(data_start)?;
+        let data = picture_bytes.get(data_start + 4..data_start + 4 + data_length)?.to_vec();
+
+        Some(Picture {
+            mime_type,
+            picture_type,
+            description,
+            data
+        })
+    }
+}
+
+impl Display for Picture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.picture_type {
+            3 => "Front cover",
+            4 => "Back cover",
+            6 => "Media",
+            8 => "Artist",
+            _ => "Picture"
+        };
+
+        if self.description.is_empty() {
+            write!(f, "{} ({}, {} KB)", kind, self.mime_type, self.data.len() / 1024)
+        } else {
+            write!(f, "{} \"{}\" ({}, {} KB)", kind, self.description, self.mime_type, self.data.len() / 1024)
+        }
+    }
+}
+
+// standard base64 with optional padding, None on anything else
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for character in text.trim().trim_end_matches('=').bytes() {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None
+        };
+
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+// INFO strings are NUL terminated and may be padded with extra NULs
+fn info_text(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    let text = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_base64, Metadata};
+    use crate::wav::Endianness;
+
+    fn info_chunk(id: &[u8; 4], value: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((value.len() as u32).to_le_bytes());
+        bytes.extend(value);
+        if value.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_info_list() {
+        let info_bytes = [
+            info_chunk(b"INAM", b"Song\0"),
+            info_chunk(b"IART", b"Band\0\0"),
+            info_chunk(b"ITRK", b"3/12\0"),
+            info_chunk(b"ISFT", b"Encoder\0"),
+            info_chunk(b"ICMT", b"  \0")
+        ].concat();
+        let metadata = Metadata::from_info_list(&info_bytes, Endianness::Little);
+
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Band"));
+        assert_eq!(metadata.track_number(), Some(3));
+        assert_eq!(metadata.comment, None);
+    }
+
+    #[test]
+    fn id3_fields_win_over_info() {
+        let id3 = Metadata { title: Some(String::from("Tagged")), ..Metadata::default() };
+        let info = Metadata { title: Some(String::from("Info")), album: Some(String::from("Album")), ..Metadata::default() };
+        let metadata = id3.or(info);
+
+        assert_eq!(metadata.title.as_deref(), Some("Tagged"));
+        assert_eq!(metadata.album.as_deref(), Some("Album"));
+    }
+
+    #[test]
+    fn reads_truncated_info_list() {
+        // a size running past the end keeps what's there, a cut off sub chunk header is ignored
+        let mut info_bytes = info_chunk(b"INAM", b"Song\0");
+        info_bytes.extend(b"IART");
+        info_bytes.extend(100u32.to_le_bytes());
+        info_bytes.extend(b"Ba");
+        info_bytes.extend(b"IGN");
+        let metadata = Metadata::from_info_list(&info_bytes, Endianness::Little);
+
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("BaIGN"));
+        assert_eq!(Metadata::from_info_list(&[0x49, 0x4E], Endianness::Little).title, None);
+    }
+
+    fn vorbis_comment(comments: &[String]) -> Vec<u8> {
+        let mut comment_bytes = 0u32.to_le_bytes().to_vec();
+        comment_bytes.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            comment_bytes.extend((comment.len() as u32).to_le_bytes());
+            comment_bytes.extend(comment.as_bytes());
+        }
+        comment_bytes
+    }
+
+    fn picture_block(picture_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = picture_type.to_be_bytes().to_vec();
+        bytes.extend(9u32.to_be_bytes());
+        bytes.extend(b"image/png");
+        bytes.extend(5u32.to_be_bytes());
+        bytes.extend(b"Cover");
+        bytes.extend([0; 16]);
+        bytes.extend((data.len() as u32).to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for group in bytes.chunks(3) {
+            let bits = group.iter().enumerate().fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - 8 * index));
+            for index in 0..4 {
+                text.push(if index <= group.len() { ALPHABET[(bits >> (18 - 6 * index)) as usize & 0x3F] as char } else { '=' });
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn decodes_base64() {
+        assert_eq!(decode_base64("TWFu").as_deref(), Some(&b"Man"[..]));
+        assert_eq!(decode_base64("TWE=").as_deref(), Some(&b"Ma"[..]));
+        assert_eq!(decode_base64(" TQ==\n").as_deref(), Some(&b"M"[..]));
+        assert_eq!(decode_base64("+/+/").as_deref(), Some(&[0xFB, 0xFF, 0xBF][..]));
+        assert_eq!(decode_base64("TW-u"), None);
+        assert_eq!(decode_base64("T=Fu"), None);
+    }
+
+    #[test]
+    fn reads_metadata_block_picture() {
+        let metadata = Metadata::from_vorbis_comment(&vorbis_comment(&[
+            format!("METADATA_BLOCK_PICTURE={}", base64(&picture_block(4, b"back"))),
+            format!("metadata_block_picture={}", base64(&picture_block(3, b"front"))),
+            format!("METADATA_BLOCK_PICTURE={}", base64(&picture_block(0, b"other"))),
+            String::from("title=Song")
+        ]));
+
+        // the front cover wins over pictures before and after it
+        let picture = metadata.picture.unwrap();
+        assert_eq!((picture.mime_type.as_str(), picture.picture_type, picture.description.as_str()), ("image/png", 3, "Cover"));
+        assert_eq!(picture.data, b"front");
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+
+        // broken base64 or a cut off block are skipped
+        let mut truncated = picture_block(3, b"front");
+        truncated.truncate(truncated.len() - 2);
+        let metadata = Metadata::from_vorbis_comment(&vorbis_comment(&[
+            String::from("METADATA_BLOCK_PICTURE=not base64!"),
+            format!("METADATA_BLOCK_PICTURE={}", base64(&truncated))
+        ]));
+        assert!(metadata.picture.is_none());
+    }
+
+    #[test]
+    fn reads_truncated_vorbis_comment() {
+        let mut comment_bytes = 0u32.to_le_bytes().to_vec();
+        comment_bytes.extend(3u32.to_le_bytes());
+        for comment in ["GENRE=Jazz", "no separator"] {
+            comment_bytes.extend((comment.len() as u32).to_le_bytes());
+            comment_bytes.extend(comment.as_bytes());
+        }
+        // the third comment claims more bytes than are left
+        comment_bytes.extend(100u32.to_le_bytes());
+        comment_bytes.extend(b"TITLE=Cut");
+        let metadata = Metadata::from_vorbis_comment(&comment_bytes);
+
+        assert_eq!(metadata.genre.as_deref(), Some("Jazz"));
+        assert_eq!(metadata.title, None);
+        assert_eq!(Metadata::from_vorbis_comment(&[0xFF, 0xFF, 0xFF, 0x7F]).title, None);
+    }
+}
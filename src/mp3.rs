@@ -0,0 +1,710 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use minimp3_sys::{mp3dec_decode_frame, mp3dec_frame_info_t, mp3dec_init, mp3dec_t, MINIMP3_MAX_SAMPLES_PER_FRAME};
+
+use crate::decoder::{Decoder, DecoderError, StreamInfo};
+use crate::id3::{read_id3v1, read_id3v2};
+use crate::metadata::Metadata;
+
+// samples every layer III decoder outputs before the first encoded one, on top of the encoder delay
+const DECODER_DELAY: u64 = 529;
+// frames decoded and dropped ahead of a seek target so the bit reservoir and the synthesis filter are filled again
+const SEEK_PREROLL_FRAMES: usize = 10;
+// the decoder wants a few frames ahead of the one it decodes to confirm the sync
+const INPUT_BUFFER_SIZE: usize = 64 * 1024;
+const INPUT_REFILL: usize = 16 * 1024;
+
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320]
+];
+const BITRATES_V2: [[u32; 15]; 3] = [
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160]
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MpegVersion {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    version: MpegVersion,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    channels: u16,
+    samples_per_frame: u64,
+    length: usize
+}
+
+impl FrameHeader {
+    // None for anything that is not a valid frame header, free format streams included
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version = match (bytes[1] >> 3) & 0x03 {
+            0 => MpegVersion::Mpeg25,
+            2 => MpegVersion::Mpeg2,
+            3 => MpegVersion::Mpeg1,
+            _ => return None
+        };
+        let layer = match (bytes[1] >> 1) & 0x03 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None
+        };
+
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let sample_rate_index = ((bytes[2] >> 2) & 0x03) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+
+        let bitrates = if version == MpegVersion::Mpeg1 { &BITRATES_V1 } else { &BITRATES_V2 };
+        let bitrate = bitrates[layer as usize - 1][bitrate_index];
+        let sample_rate = [44100, 48000, 32000][sample_rate_index] >> match version {
+            MpegVersion::Mpeg1 => 0,
+            MpegVersion::Mpeg2 => 1,
+            MpegVersion::Mpeg25 => 2
+        };
+        let padding = ((bytes[2] >> 1) & 0x01) as usize;
+        let channels = if bytes[3] >> 6 == 3 { 1 } else { 2 };
+
+        let samples_per_frame = match layer {
+            1 => 384,
+            3 if version != MpegVersion::Mpeg1 => 576,
+            _ => 1152
+        };
+        let length = if layer == 1 {
+            (12 * bitrate as usize * 1000 / sample_rate as usize + padding) * 4
+        } else {
+            samples_per_frame as usize / 8 * bitrate as usize * 1000 / sample_rate as usize + padding
+        };
+
+        Some(FrameHeader {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            channels,
+            samples_per_frame,
+            length
+        })
+    }
+
+    // where a Xing or Info header sits, right behind the layer III side info
+    fn side_info_end(&self) -> usize {
+        4 + match (self.version, self.channels) {
+            (MpegVersion::Mpeg1, 1) => 17,
+            (MpegVersion::Mpeg1, _) => 32,
+            (_, 1) => 9,
+            _ => 17
+        }
+    }
+}
+
+// what the Xing/Info or VBRI header in the first frame tells about the stream
+#[derive(Debug, Default)]
+struct VbrHeader {
+    kind: &'static str,
+    frames: Option<u64>,
+    encoder: Option<String>,
+    // samples the encoder added in front of and behind the audio, from the LAME tag
+    delay: Option<u64>,
+    padding: Option<u64>
+}
+
+// Decoder backend for MPEG audio files, minimp3 decodes the frames.
+// Duration and gapless trimming come from the Xing/Info, LAME or VBRI header when the encoder wrote one,
+// otherwise the duration is estimated from the bitrate of the first frame.
+pub struct Mp3Decoder {
+    info: StreamInfo,
+    metadata: Metadata,
+    file: File,
+    mp3dec: Box<mp3dec_t>,
+    pcm: Vec<i16>,
+    input: Vec<u8>,
+    input_start: usize,
+    file_position: u64,
+    // the first frame after the Xing/VBRI frame and the end of the frames, before any trailing tags
+    audio_start: u64,
+    audio_end: u64,
+    samples_per_frame: u64,
+    // decoder output before this sample is encoder and decoder delay, and after `end_sample` encoder padding
+    start_padding: u64,
+    end_sample: Option<u64>,
+    // byte offset of every frame, only scanned once the first seek needs it
+    frame_offsets: Vec<u64>,
+    decoded: Vec<f32>,
+    decoded_index: usize,
+    // decoder output position of the next decoded frame, and the position a seek wants to start at
+    next_sample: u64,
+    skip_until: u64
+}
+
+impl Decoder for Mp3Decoder {
+    fn open(path: &Path) -> Result<Self, DecoderError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let file_length = reader.get_ref().metadata()?.len();
+
+        let mut metadata = Metadata::default();
+        let mut audio_start = 0;
+
+        let mut id3_header = [0u8; 10];
+        if file_length >= 10 {
+            reader.read_exact(&mut id3_header)?;
+        }
+        if &id3_header[0..3] == b"ID3" {
+            let tag_size = id3_header[6..10].iter().fold(0u64, |size, byte| (size << 7) | (*byte & 0x7F) as u64);
+            // a footer repeats the header behind the tag
+            let footer_size = if id3_header[5] & 0x10 != 0 { 10 } else { 0 };
+
+            let mut tag_bytes = id3_header.to_vec();
+            tag_bytes.resize(10 + tag_size as usize, 0);
+            reader.read_exact(&mut tag_bytes[10..])?;
+            metadata = read_id3v2(&tag_bytes).unwrap_or_default();
+
+            audio_start = 10 + tag_size + footer_size;
+        }
+
+        let audio_end = trailing_tags_start(&mut reader, audio_start, file_length, &mut metadata)?;
+        if audio_start >= audio_end {
+            return Err(DecoderError::Mp3("no audio data"));
+        }
+
+        // the first frame, skipping any junk in front of it
+        reader.seek(SeekFrom::Start(audio_start))?;
+        let mut probe = vec![0u8; (audio_end - audio_start).min(INPUT_BUFFER_SIZE as u64) as usize];
+        reader.read_exact(&mut probe)?;
+        let Some((first_offset, header)) = find_first_frame(&probe) else {
+            return Err(DecoderError::Mp3("no MPEG audio frame found"));
+        };
+        let first_frame = &probe[first_offset..(first_offset + header.length).min(probe.len())];
+        audio_start += first_offset as u64;
+
+        let vbr_header = read_vbr_header(first_frame, &header);
+        // the Xing/VBRI frame holds no audio
+        if vbr_header.is_some() {
+            audio_start += header.length as u64;
+        }
+        if audio_start >= audio_end {
+            return Err(DecoderError::Mp3("no audio data"));
+        }
+        let vbr_header = vbr_header.unwrap_or_default();
+
+        let encoded_frames = vbr_header.frames.unwrap_or_else(|| {
+            let average_length = header.samples_per_frame as f64 / 8.0 * header.bitrate as f64 * 1000.0 / header.sample_rate as f64;
+            ((audio_end - audio_start) as f64 / average_length).round() as u64
+        });
+        let encoded_samples = encoded_frames * header.samples_per_frame;
+
+        let (start_padding, end_sample, frames) = match (vbr_header.delay, vbr_header.padding) {
+            (Some(delay), Some(padding)) if delay + padding < encoded_samples => {
+                let frames = encoded_samples - delay - padding;
+                (delay + DECODER_DELAY, Some(delay + DECODER_DELAY + frames), frames)
+            }
+            _ => (0, None, encoded_samples)
+        };
+
+        let layer = ["I", "II", "III"][header.layer as usize - 1];
+        let version = match header.version {
+            MpegVersion::Mpeg1 => "MPEG-1",
+            MpegVersion::Mpeg2 => "MPEG-2",
+            MpegVersion::Mpeg25 => "MPEG-2.5"
+        };
+        let mut details = vec![
+            (String::from("Format"), format!("{} Layer {}", version, layer)),
+            (String::from("Bitrate"), if vbr_header.kind.is_empty() {
+                format!("{} kbps", header.bitrate)
+            } else {
+                let seconds = frames as f64 / header.sample_rate as f64;
+                format!("{:.0} kbps average ({} header)", (audio_end - audio_start) as f64 * 8.0 / seconds / 1000.0, vbr_header.kind)
+            })
+        ];
+        if let Some(encoder) = vbr_header.encoder {
+            details.push((String::from("Encoder"), encoder));
+        }
+        if let (Some(delay), Some(padding)) = (vbr_header.delay, vbr_header.padding) {
+            details.push((String::from("Gapless"), format!("{} samples delay, {} samples padding", delay, padding)));
+        }
+
+        let info = StreamInfo {
+            sample_rate: header.sample_rate,
+            channels: header.channels,
+            channel_mask: if header.channels == 1 { 0x4 } else { 0x3 },
+            frames,
+            markers: Vec::new(),
+            loops: Vec::new(),
+            time_reference: None,
+            timecode_rate: None,
+            details
+        };
+
+        let mut decoder = Mp3Decoder {
+            info,
+            metadata,
+            file: reader.into_inner(),
+            mp3dec: Box::new(empty_mp3dec()),
+            pcm: vec![0; MINIMP3_MAX_SAMPLES_PER_FRAME as usize],
+            input: Vec::with_capacity(INPUT_BUFFER_SIZE),
+            input_start: 0,
+            file_position: 0,
+            audio_start,
+            audio_end,
+            samples_per_frame: header.samples_per_frame,
+            start_padding,
+            end_sample,
+            frame_offsets: Vec::new(),
+            decoded: Vec::new(),
+            decoded_index: 0,
+            next_sample: 0,
+            skip_until: 0
+        };
+        decoder.restart(audio_start, 0)?;
+
+        Ok(decoder)
+    }
+
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError> {
+        let channels = self.info.channels as usize;
+
+        if self.decoded_index >= self.decoded.len() && !self.decode_frame()? {
+            return Ok(0);
+        }
+
+        let frames = ((self.decoded.len() - self.decoded_index) / channels).min(max_frames);
+        let end = self.decoded_index + frames * channels;
+        samples.extend_from_slice(&self.decoded[self.decoded_index..end]);
+        self.decoded_index = end;
+
+        Ok(frames)
+    }
+
+    // restarts a few frames ahead of the one holding `frame` and decodes forward from there
+    fn seek(&mut self, frame: u64) -> Result<(), DecoderError> {
+        if self.frame_offsets.is_empty() {
+            self.frame_offsets = self.scan_frames()?;
+        }
+
+        let target = frame + self.start_padding;
+        let start_frame = ((target / self.samples_per_frame) as usize)
+            .saturating_sub(SEEK_PREROLL_FRAMES)
+            .min(self.frame_offsets.len());
+        let offset = self.frame_offsets.get(start_frame).copied().unwrap_or(self.audio_end);
+
+        self.restart(offset, start_frame as u64 * self.samples_per_frame)?;
+        self.skip_until = target;
+
+        Ok(())
+    }
+}
+
+impl Mp3Decoder {
+    fn restart(&mut self, offset: u64, sample: u64) -> Result<(), DecoderError> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file_position = offset;
+        self.input.clear();
+        self.input_start = 0;
+
+        unsafe { mp3dec_init(&mut *self.mp3dec) };
+
+        self.decoded.clear();
+        self.decoded_index = 0;
+        self.next_sample = sample;
+        self.skip_until = self.start_padding;
+
+        Ok(())
+    }
+
+    // decodes the next frame into `decoded`, returns false at the end of the stream
+    fn decode_frame(&mut self) -> Result<bool, DecoderError> {
+        let channels = self.info.channels as usize;
+
+        loop {
+            if self.end_sample.is_some_and(|end_sample| self.next_sample >= end_sample) {
+                return Ok(false);
+            }
+
+            if self.input.len() - self.input_start < INPUT_REFILL && self.file_position < self.audio_end {
+                self.fill_input()?;
+            }
+
+            let input = &self.input[self.input_start..];
+            if input.is_empty() {
+                return Ok(false);
+            }
+
+            let mut frame_info = mp3dec_frame_info_t {
+                frame_bytes: 0,
+                frame_offset: 0,
+                channels: 0,
+                hz: 0,
+                layer: 0,
+                bitrate_kbps: 0
+            };
+            let samples = unsafe {
+                mp3dec_decode_frame(&mut *self.mp3dec, input.as_ptr(), input.len() as i32, self.pcm.as_mut_ptr(), &mut frame_info)
+            } as usize;
+
+            if frame_info.frame_bytes == 0 {
+                // what is left is too short to hold a frame
+                return Ok(false);
+            }
+            self.input_start += frame_info.frame_bytes as usize;
+
+            // no frame found in the skipped bytes
+            if frame_info.hz == 0 {
+                continue;
+            }
+
+            let first_sample = self.next_sample;
+            let frame_channels = frame_info.channels.max(1) as usize;
+
+            // a frame whose bit reservoir lies before a seek is still counted, as silence
+            let frame_samples = if samples > 0 { samples as u64 } else { self.samples_per_frame };
+            self.next_sample += frame_samples;
+
+            if self.next_sample <= self.skip_until {
+                continue;
+            }
+
+            let last_sample = self.end_sample.map_or(self.next_sample, |end_sample| end_sample.min(self.next_sample));
+            let skip = self.skip_until.saturating_sub(first_sample);
+
+            // the channel count is fixed at the first frame, a mono frame in a stereo stream is played on both sides
+            self.decoded.clear();
+            for sample in skip..last_sample - first_sample {
+                for channel in 0..channels {
+                    let value = if samples > 0 {
+                        self.pcm[sample as usize * frame_channels + channel.min(frame_channels - 1)]
+                    } else {
+                        0
+                    };
+                    self.decoded.push(value as f32 / 32768.0);
+                }
+            }
+            self.decoded_index = 0;
+
+            return Ok(true);
+        }
+    }
+
+    // moves the unread input to the front and reads up to the end of the frames
+    fn fill_input(&mut self) -> Result<(), DecoderError> {
+        self.input.drain(..self.input_start);
+        self.input_start = 0;
+
+        let length = ((INPUT_BUFFER_SIZE - self.input.len()) as u64).min(self.audio_end - self.file_position) as usize;
+        let filled = self.input.len();
+        self.input.resize(filled + length, 0);
+        self.file.read_exact(&mut self.input[filled..])?;
+        self.file_position += length as u64;
+
+        Ok(())
+    }
+
+    // walks the frame headers from the first audio frame, resyncing byte by byte over anything else.
+    // this moves the file, the seek calling it restarts decoding anyway
+    fn scan_frames(&mut self) -> Result<Vec<u64>, DecoderError> {
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(self.audio_start))?;
+
+        let mut offsets = Vec::new();
+        let mut offset = self.audio_start;
+        let mut header = [0u8; 4];
+
+        while offset + 4 <= self.audio_end {
+            reader.read_exact(&mut header)?;
+
+            match FrameHeader::parse(&header) {
+                Some(frame) if offset + frame.length as u64 <= self.audio_end => {
+                    offsets.push(offset);
+                    offset += frame.length as u64;
+                    reader.seek_relative(frame.length as i64 - 4)?;
+                }
+                _ => {
+                    offset += 1;
+                    reader.seek_relative(-3)?;
+                }
+            }
+        }
+
+        Ok(offsets)
+    }
+}
+
+fn empty_mp3dec() -> mp3dec_t {
+    let mut mp3dec = mp3dec_t {
+        mdct_overlap: [[0.0; 288]; 2],
+        qmf_state: [0.0; 960],
+        reserv: 0,
+        free_format_bytes: 0,
+        header: [0; 4],
+        reserv_buf: [0; 511]
+    };
+    unsafe { mp3dec_init(&mut mp3dec) };
+    mp3dec
+}
+
+// a frame only counts when the next one follows right behind it, so stray sync bytes in junk are skipped
+fn find_first_frame(bytes: &[u8]) -> Option<(usize, FrameHeader)> {
+    (0..bytes.len().saturating_sub(4)).find_map(|offset| {
+        let header = FrameHeader::parse(&bytes[offset..])?;
+        let next = offset + header.length;
+        let followed = next + 4 > bytes.len() || FrameHeader::parse(&bytes[next..])
+            .is_some_and(|next| next.sample_rate == header.sample_rate && next.layer == header.layer);
+
+        followed.then_some((offset, header))
+    })
+}
+
+// ID3v1 and APEv2 tags sit behind the last frame, returns where they start
+fn trailing_tags_start(reader: &mut BufReader<File>, audio_start: u64, file_length: u64, metadata: &mut Metadata) -> Result<u64, DecoderError> {
+    let mut audio_end = file_length;
+
+    if audio_end >= audio_start + 128 {
+        let mut tag_bytes = [0u8; 128];
+        reader.seek(SeekFrom::Start(audio_end - 128))?;
+        reader.read_exact(&mut tag_bytes)?;
+
+        if let Some(id3v1) = read_id3v1(&tag_bytes) {
+            // ID3v2 wins where both tags have a field
+            *metadata = std::mem::take(metadata).or(id3v1);
+            audio_end -= 128;
+        }
+    }
+
+    if audio_end >= audio_start + 32 {
+        let mut footer = [0u8; 32];
+        reader.seek(SeekFrom::Start(audio_end - 32))?;
+        reader.read_exact(&mut footer)?;
+
+        if &footer[0..8] == b"APETAGEX" {
+            let tag_size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+            let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+            let header_size = if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+            audio_end = audio_end.saturating_sub(tag_size + header_size).max(audio_start);
+        }
+    }
+
+    Ok(audio_end)
+}
+
+fn read_vbr_header(frame: &[u8], header: &FrameHeader) -> Option<VbrHeader> {
+    let be_u32 = |at: usize| -> Option<u32> {
+        let bytes = frame.get(at..at + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    let xing_start = header.side_info_end();
+    let xing_id = frame.get(xing_start..xing_start + 4);
+
+    if xing_id == Some(b"Xing") || xing_id == Some(b"Info") {
+        let flags = be_u32(xing_start + 4)?;
+        let mut offset = xing_start + 8;
+
+        let mut vbr_header = VbrHeader {
+            kind: if xing_id == Some(b"Xing") { "Xing" } else { "Info" },
+            ..VbrHeader::default()
+        };
+
+        if flags & 0x1 != 0 {
+            vbr_header.frames = be_u32(offset).map(|frames| frames as u64);
+            offset += 4;
+        }
+        // byte count, table of contents and quality are not needed with the exact frame scan
+        if flags & 0x2 != 0 {
+            offset += 4;
+        }
+        if flags & 0x4 != 0 {
+            offset += 100;
+        }
+        if flags & 0x8 != 0 {
+            offset += 4;
+        }
+
+        // the LAME tag follows with a nine character encoder version, delay and padding are 12 bits each 21 bytes in
+        if let Some(version) = frame.get(offset..offset + 9) {
+            let encoder = String::from_utf8_lossy(version).trim_end_matches(['\0', ' ']).to_string();
+            let is_lame_tag = ["LAME", "Lavf", "Lavc", "GOGO", "L3.99"].iter().any(|prefix| encoder.starts_with(prefix));
+
+            if is_lame_tag {
+                if let Some(gapless) = frame.get(offset + 21..offset + 24) {
+                    vbr_header.delay = Some(((gapless[0] as u64) << 4) | (gapless[1] as u64 >> 4));
+                    vbr_header.padding = Some((((gapless[1] & 0x0F) as u64) << 8) | gapless[2] as u64);
+                }
+                vbr_header.encoder = Some(encoder);
+            }
+        }
+
+        return Some(vbr_header);
+    }
+
+    // Fraunhofer's VBRI header always sits 32 bytes behind the frame header
+    if frame.get(36..40) == Some(b"VBRI") {
+        return Some(VbrHeader {
+            kind: "VBRI",
+            frames: be_u32(50).map(|frames| frames as u64),
+            ..VbrHeader::default()
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{read_vbr_header, FrameHeader, Mp3Decoder, MpegVersion, DECODER_DELAY};
+    use crate::decoder::{Decoder, DecoderError};
+
+    // MPEG-1 layer III, 128 kbps, 44100 Hz, joint stereo, no CRC and no padding
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x40];
+
+    // a frame of zeroed side info and main data decodes to silence
+    fn silent_frame() -> Vec<u8> {
+        let mut frame = HEADER.to_vec();
+        frame.resize(417, 0);
+        frame
+    }
+
+    // an Info frame counting `frames` frames with a LAME tag holding the delay and padding
+    fn info_frame(frames: u32, gapless: Option<(u16, u16)>) -> Vec<u8> {
+        let mut frame = silent_frame();
+        frame[36..40].copy_from_slice(b"Info");
+        // frame count and quality
+        frame[40..44].copy_from_slice(&0x9u32.to_be_bytes());
+        frame[44..48].copy_from_slice(&frames.to_be_bytes());
+        if let Some((delay, padding)) = gapless {
+            frame[52..61].copy_from_slice(b"LAME3.100");
+            frame[73..76].copy_from_slice(&[(delay >> 4) as u8, ((delay << 4) as u8) | (padding >> 8) as u8, padding as u8]);
+        }
+        frame
+    }
+
+    fn write_mp3(name: &str, frames: &[Vec<u8>]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.mp3", name, std::process::id()));
+        std::fs::write(&path, frames.concat()).unwrap();
+        path
+    }
+
+    fn open(path: &Path) -> Result<Mp3Decoder, DecoderError> {
+        let result = Mp3Decoder::open(path);
+        std::fs::remove_file(path).unwrap();
+        result
+    }
+
+    fn read_to_end(decoder: &mut Mp3Decoder) -> usize {
+        let mut samples = Vec::new();
+        while decoder.read_frames(&mut samples, 4096).unwrap() > 0 {}
+        samples.len() / 2
+    }
+
+    #[test]
+    fn parses_frame_headers() {
+        let header = FrameHeader::parse(&HEADER).unwrap();
+        assert_eq!((header.version, header.layer, header.bitrate, header.sample_rate), (MpegVersion::Mpeg1, 3, 128, 44100));
+        assert_eq!((header.channels, header.samples_per_frame, header.length, header.side_info_end()), (2, 1152, 417, 36));
+
+        // padded mono
+        let header = FrameHeader::parse(&[0xFF, 0xFB, 0x92, 0xC0]).unwrap();
+        assert_eq!((header.channels, header.length, header.side_info_end()), (1, 418, 21));
+
+        // MPEG-2 layer III at 64 kbps and 22050 Hz
+        let header = FrameHeader::parse(&[0xFF, 0xF3, 0x80, 0x00]).unwrap();
+        assert_eq!((header.version, header.sample_rate, header.samples_per_frame, header.length, header.side_info_end()), (MpegVersion::Mpeg2, 22050, 576, 208, 21));
+
+        // MPEG-1 layer I lengths count four byte slots
+        let header = FrameHeader::parse(&[0xFF, 0xFF, 0x10, 0x00]).unwrap();
+        assert_eq!((header.layer, header.bitrate, header.samples_per_frame, header.length), (1, 32, 384, 32));
+    }
+
+    #[test]
+    fn rejects_invalid_frame_headers() {
+        assert!(FrameHeader::parse(&HEADER[..3]).is_none());
+        assert!(FrameHeader::parse(&[0xFF, 0x7B, 0x90, 0x40]).is_none());
+        // reserved version and layer
+        assert!(FrameHeader::parse(&[0xFF, 0xEB, 0x90, 0x40]).is_none());
+        assert!(FrameHeader::parse(&[0xFF, 0xF9, 0x90, 0x40]).is_none());
+        // free format, bad bitrate and reserved sample rate
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0x00, 0x40]).is_none());
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0x40]).is_none());
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0x9C, 0x40]).is_none());
+    }
+
+    #[test]
+    fn reads_xing_and_lame_headers() {
+        let header = FrameHeader::parse(&HEADER).unwrap();
+
+        let vbr_header = read_vbr_header(&info_frame(10, Some((576, 1000))), &header).unwrap();
+        assert_eq!((vbr_header.kind, vbr_header.frames), ("Info", Some(10)));
+        assert_eq!(vbr_header.encoder.as_deref(), Some("LAME3.100"));
+        assert_eq!((vbr_header.delay, vbr_header.padding), (Some(576), Some(1000)));
+
+        let vbr_header = read_vbr_header(&info_frame(10, None), &header).unwrap();
+        assert_eq!((vbr_header.frames, vbr_header.encoder, vbr_header.delay), (Some(10), None, None));
+
+        let mut frame = silent_frame();
+        frame[36..40].copy_from_slice(b"VBRI");
+        frame[50..54].copy_from_slice(&25u32.to_be_bytes());
+        let vbr_header = read_vbr_header(&frame, &header).unwrap();
+        assert_eq!((vbr_header.kind, vbr_header.frames), ("VBRI", Some(25)));
+
+        assert!(read_vbr_header(&silent_frame(), &header).is_none());
+        // a Xing id without room for the flags
+        assert!(read_vbr_header(&info_frame(10, None)[..42], &header).is_none());
+    }
+
+    #[test]
+    fn trims_gapless_delay_and_padding() {
+        let mut frames = vec![info_frame(10, Some((576, 1000)))];
+        frames.extend(std::iter::repeat_n(silent_frame(), 10));
+        let mut decoder = open(&write_mp3("mp3-gapless", &frames)).unwrap();
+
+        assert_eq!(decoder.info().frames, 10 * 1152 - 576 - 1000);
+        assert_eq!(decoder.start_padding, 576 + DECODER_DELAY);
+        assert_eq!(read_to_end(&mut decoder), 10 * 1152 - 576 - 1000);
+
+        decoder.seek(5000).unwrap();
+        assert_eq!(read_to_end(&mut decoder), 10 * 1152 - 576 - 1000 - 5000);
+
+        // without a LAME tag nothing is trimmed
+        let mut frames = vec![info_frame(10, None)];
+        frames.extend(std::iter::repeat_n(silent_frame(), 10));
+        let mut decoder = open(&write_mp3("mp3-untrimmed", &frames)).unwrap();
+        assert_eq!(decoder.info().frames, 10 * 1152);
+        assert_eq!(read_to_end(&mut decoder), 10 * 1152);
+    }
+
+    #[test]
+    fn rejects_files_without_audio() {
+        let result = open(&write_mp3("mp3-info-only", &[info_frame(0, None)]));
+        assert!(matches!(result, Err(DecoderError::Mp3("no audio data"))));
+
+        let result = open(&write_mp3("mp3-junk", &[vec![0x55; 2000]]));
+        assert!(matches!(result, Err(DecoderError::Mp3("no MPEG audio frame found"))));
+
+        let result = open(&write_mp3("mp3-empty", &[]));
+        assert!(matches!(result, Err(DecoderError::Mp3("no audio data"))));
+    }
+}
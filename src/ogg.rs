@@ -0,0 +1,617 @@
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::decoder::{Decoder, DecoderError, StreamInfo};
+use crate::metadata::Metadata;
+use crate::opus::{OpusDecoder, OPUS_SAMPLE_RATE};
+use crate::vorbis::VorbisDecoder;
+
+const PAGE_CONTINUED: u8 = 0x01;
+const PAGE_FIRST: u8 = 0x02;
+const PAGE_LAST: u8 = 0x04;
+// pages on which no packet ends carry no granule position
+const NO_GRANULE: u64 = u64::MAX;
+
+// Opus needs 80 ms of decoded audio in front of a seek target before its output converges
+const OPUS_SEEK_PREROLL: u64 = 3840;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+// the speakers of the Vorbis channel order, which Opus shares, as positions in the WAVE_FORMAT_EXTENSIBLE order
+const VORBIS_CHANNEL_ORDER: [&[usize]; 8] = [
+    &[0],
+    &[0, 1],
+    &[0, 2, 1],
+    &[0, 1, 2, 3],
+    &[0, 2, 1, 3, 4],
+    &[0, 2, 1, 4, 5, 3],
+    &[0, 2, 1, 5, 6, 4, 3],
+    &[0, 2, 1, 6, 7, 4, 5, 3]
+];
+const VORBIS_CHANNEL_MASKS: [u32; 8] = [0x4, 0x3, 0x7, 0x33, 0x37, 0x3F, 0x70F, 0x63F];
+
+struct Page {
+    flags: u8,
+    granule: u64,
+    serial: u32,
+    segments: Vec<u8>,
+    body: Vec<u8>
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PagePosition {
+    offset: u64,
+    granule: u64
+}
+
+enum OggCodec {
+    Vorbis(Box<VorbisDecoder>),
+    Opus(OpusDecoder)
+}
+
+impl OggCodec {
+    fn decode_packet(&mut self, packet: &[u8], samples: &mut Vec<f32>) -> Result<usize, DecoderError> {
+        match self {
+            OggCodec::Vorbis(decoder) => decoder.decode_packet(packet, samples),
+            OggCodec::Opus(decoder) => decoder.decode_packet(packet, samples)
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            OggCodec::Vorbis(decoder) => decoder.reset(),
+            OggCodec::Opus(decoder) => decoder.reset()
+        }
+    }
+}
+
+// Decoder backend for Ogg Vorbis and Ogg Opus files. The pages are demuxed here and the packets handed to the codec.
+// Positions are granule positions: the page on which a packet ends tells the position right after it,
+// so decoded audio is only placed once such a page was read. Only the first Vorbis or Opus stream is played.
+pub struct OggDecoder {
+    info: StreamInfo,
+    metadata: Metadata,
+    reader: BufReader<File>,
+    codec: OggCodec,
+    serial: u32,
+    // the first page after the headers, and the granule position of the last page
+    audio_offset: u64,
+    end_granule: u64,
+    // granule positions before this only prime the decoder, Opus calls it pre-skip
+    start_granule: u64,
+    // the page of every granule position, only scanned once the first seek needs it
+    pages: Vec<PagePosition>,
+    // a packet continued on the next page
+    partial_packet: Vec<u8>,
+    // frames decoded since the last placed page, in Vorbis channel order
+    pending: Vec<f32>,
+    channel_order: Vec<usize>,
+    decoded: Vec<f32>,
+    decoded_index: usize,
+    // granule position of the next decoded frame, unknown right after a seek
+    position: Option<u64>,
+    skip_until: u64,
+    ended: bool
+}
+
+impl Decoder for OggDecoder {
+    fn open(path: &Path) -> Result<Self, DecoderError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        // the first page of the stream holds the identification header
+        let (serial, ident_packet) = loop {
+            let Some(page) = read_page(&mut reader)? else {
+                return Err(DecoderError::Ogg("no Vorbis or Opus stream found"));
+            };
+            if page.flags & PAGE_FIRST == 0 {
+                continue;
+            }
+            let Some(packet) = split_packets(&page, &mut Vec::new()).into_iter().next() else {
+                continue;
+            };
+            if packet.starts_with(b"\x01vorbis") || packet.starts_with(b"OpusHead") {
+                break (page.serial, packet);
+            }
+        };
+
+        // Vorbis has comment and setup headers, Opus only a comment header
+        let is_vorbis = ident_packet.starts_with(b"\x01vorbis");
+        let header_count = if is_vorbis { 2 } else { 1 };
+
+        let mut headers: Vec<Vec<u8>> = Vec::new();
+        let mut partial_packet = Vec::new();
+        while headers.len() < header_count {
+            let Some(page) = read_page(&mut reader)? else {
+                return Err(DecoderError::Ogg("stream ends inside the headers"));
+            };
+            if page.serial == serial {
+                headers.extend(split_packets(&page, &mut partial_packet));
+            }
+        }
+        let audio_offset = reader.stream_position()?;
+
+        let (codec, comment, mut details) = if is_vorbis {
+            let decoder = VorbisDecoder::new(&ident_packet, &headers[1])?;
+            let details = vec![
+                (String::from("Format"), format!("Ogg Vorbis, {} kbps nominal", decoder.nominal_bitrate() / 1000))
+            ];
+            (OggCodec::Vorbis(Box::new(decoder)), headers[0].get(7..).unwrap_or_default(), details)
+        } else {
+            let decoder = OpusDecoder::new(&ident_packet)?;
+            let details = vec![
+                (String::from("Format"), format!("Ogg Opus, mapping family {}", decoder.mapping_family())),
+                (String::from("Source rate"), format!("{} Hz", decoder.input_sample_rate()))
+            ];
+            (OggCodec::Opus(decoder), headers[0].get(8..).unwrap_or_default(), details)
+        };
+
+        let (sample_rate, channels, start_granule) = match &codec {
+            OggCodec::Vorbis(decoder) => (decoder.sample_rate(), decoder.channels(), 0),
+            OggCodec::Opus(decoder) => (OPUS_SAMPLE_RATE, decoder.channels(), decoder.pre_skip())
+        };
+
+        let metadata = if comment.is_empty() { Metadata::default() } else { Metadata::from_vorbis_comment(comment) };
+        if let Some(vendor) = comment_vendor(comment) {
+            details.push((String::from("Encoder"), vendor));
+        }
+
+        let end_granule = last_granule(&mut reader, serial)?.unwrap_or(NO_GRANULE);
+        let frames = if end_granule == NO_GRANULE { 0 } else { end_granule.saturating_sub(start_granule) };
+
+        let (channel_order, channel_mask) = match (channels as usize).checked_sub(1).filter(|index| *index < VORBIS_CHANNEL_ORDER.len()) {
+            Some(index) => (VORBIS_CHANNEL_ORDER[index].to_vec(), VORBIS_CHANNEL_MASKS[index]),
+            None => ((0..channels as usize).collect(), 0)
+        };
+
+        let info = StreamInfo {
+            sample_rate,
+            channels,
+            channel_mask,
+            frames,
+            markers: Vec::new(),
+            loops: Vec::new(),
+            time_reference: None,
+            timecode_rate: None,
+            details
+        };
+
+        reader.seek(SeekFrom::Start(audio_offset))?;
+
+        Ok(OggDecoder {
+            info,
+            metadata,
+            reader,
+            codec,
+            serial,
+            audio_offset,
+            end_granule,
+            start_granule,
+            pages: Vec::new(),
+            partial_packet,
+            pending: Vec::new(),
+            channel_order,
+            decoded: Vec::new(),
+            decoded_index: 0,
+            position: Some(0),
+            skip_until: start_granule,
+            ended: false
+        })
+    }
+
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError> {
+        let channels = self.info.channels as usize;
+
+        if self.decoded_index >= self.decoded.len() && !self.decode_page()? {
+            return Ok(0);
+        }
+
+        let frames = ((self.decoded.len() - self.decoded_index) / channels).min(max_frames);
+        let end = self.decoded_index + frames * channels;
+        samples.extend_from_slice(&self.decoded[self.decoded_index..end]);
+        self.decoded_index = end;
+
+        Ok(frames)
+    }
+
+    // restarts a page ahead of the last one that ends before the target, so the first packets after it only prime the decoder
+    fn seek(&mut self, frame: u64) -> Result<(), DecoderError> {
+        if self.pages.is_empty() {
+            self.pages = self.scan_pages()?;
+        }
+
+        let target = frame + self.start_granule;
+        let preroll = match self.codec {
+            OggCodec::Vorbis(_) => 0,
+            OggCodec::Opus(_) => OPUS_SEEK_PREROLL
+        };
+
+        let page = self.pages
+            .iter()
+            .rposition(|page| page.granule <= target.saturating_sub(preroll));
+        let offset = match page {
+            Some(page) if page > 0 => self.pages[page - 1].offset,
+            _ => self.audio_offset
+        };
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.codec.reset();
+        self.partial_packet.clear();
+        self.pending.clear();
+        self.decoded.clear();
+        self.decoded_index = 0;
+        self.position = if offset == self.audio_offset { Some(0) } else { None };
+        self.skip_until = target;
+        self.ended = false;
+
+        Ok(())
+    }
+}
+
+impl OggDecoder {
+    // decodes pages until one places some frames behind the skip position into `decoded`, returns false at the end of the stream
+    fn decode_page(&mut self) -> Result<bool, DecoderError> {
+        let channels = self.info.channels as usize;
+
+        while !self.ended {
+            let Some(page) = read_page(&mut self.reader)? else {
+                break;
+            };
+            if page.serial != self.serial {
+                continue;
+            }
+
+            for packet in split_packets(&page, &mut self.partial_packet) {
+                self.codec.decode_packet(&packet, &mut self.pending)?;
+            }
+
+            if page.flags & PAGE_LAST != 0 {
+                self.ended = true;
+            } else if page.granule == NO_GRANULE {
+                continue;
+            }
+
+            // right after a seek the page's granule position places the frames decoded so far
+            let frames = (self.pending.len() / channels) as u64;
+            let first = self.position.unwrap_or(page.granule.saturating_sub(frames));
+            self.position = Some(first + frames);
+
+            let start = self.skip_until.clamp(first, first + frames);
+            let end = self.end_granule.clamp(start, first + frames);
+
+            self.decoded.clear();
+            self.decoded_index = 0;
+            for frame in self.pending[(start - first) as usize * channels..(end - first) as usize * channels].chunks_exact(channels) {
+                let output = self.decoded.len();
+                self.decoded.resize(output + channels, 0.0);
+                for (channel, sample) in frame.iter().enumerate() {
+                    self.decoded[output + self.channel_order[channel]] = *sample;
+                }
+            }
+            self.pending.clear();
+
+            if !self.decoded.is_empty() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // walks the page headers of the stream, keeping where the pages with a granule position start.
+    // this moves the reader, the seek calling it repositions it anyway
+    fn scan_pages(&mut self) -> Result<Vec<PagePosition>, DecoderError> {
+        self.reader.seek(SeekFrom::Start(self.audio_offset))?;
+
+        let mut pages = Vec::new();
+        loop {
+            let offset = self.reader.stream_position()?;
+            let Some(page) = read_page(&mut self.reader)? else {
+                break;
+            };
+            if page.serial != self.serial {
+                continue;
+            }
+            if page.granule != NO_GRANULE {
+                pages.push(PagePosition { offset, granule: page.granule });
+            }
+            if page.flags & PAGE_LAST != 0 {
+                break;
+            }
+        }
+
+        Ok(pages)
+    }
+}
+
+// reads the next page with an intact checksum, None at the end of the file.
+// anything between pages is skipped until the next capture pattern
+fn read_page(reader: &mut BufReader<File>) -> Result<Option<Page>, DecoderError> {
+    loop {
+        let mut header = [0u8; 27];
+        match reader.read_exact(&mut header[..4]) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into())
+        }
+
+        if &header[..4] != b"OggS" {
+            reader.seek_relative(-3)?;
+            continue;
+        }
+
+        let mut segments = Vec::new();
+        let mut body = Vec::new();
+        let complete = reader.read_exact(&mut header[4..]).and_then(|_| {
+            segments.resize(header[26] as usize, 0);
+            reader.read_exact(&mut segments)?;
+            body.resize(segments.iter().map(|segment| *segment as usize).sum(), 0);
+            reader.read_exact(&mut body)
+        });
+        match complete {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into())
+        }
+
+        let checksum = u32::from_le_bytes([header[22], header[23], header[24], header[25]]);
+        header[22..26].fill(0);
+        let computed = [&header[..], &segments, &body].iter().fold(0, |crc, bytes| crc_update(crc, bytes));
+        if computed != checksum {
+            // resync right behind this capture pattern
+            reader.seek_relative(-((27 + segments.len() + body.len()) as i64) + 1)?;
+            continue;
+        }
+
+        return Ok(Some(Page {
+            flags: header[5],
+            granule: u64::from_le_bytes([header[6], header[7], header[8], header[9], header[10], header[11], header[12], header[13]]),
+            serial: u32::from_le_bytes([header[14], header[15], header[16], header[17]]),
+            segments,
+            body
+        }));
+    }
+}
+
+// the packets that end on this page. a packet continued from a page that was never read, like right after a seek, is dropped
+fn split_packets(page: &Page, partial_packet: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut drop_packet = page.flags & PAGE_CONTINUED != 0 && partial_packet.is_empty();
+    if page.flags & PAGE_CONTINUED == 0 {
+        partial_packet.clear();
+    }
+
+    let mut offset = 0;
+    for segment in &page.segments {
+        let length = *segment as usize;
+        partial_packet.extend_from_slice(&page.body[offset..offset + length]);
+        offset += length;
+
+        // a segment shorter than 255 bytes ends the packet
+        if length < 255 {
+            let packet = std::mem::take(partial_packet);
+            if !drop_packet {
+                packets.push(packet);
+            }
+            drop_packet = false;
+        }
+    }
+
+    if drop_packet {
+        partial_packet.clear();
+    }
+
+    packets
+}
+
+// the granule position of the stream's last page, searched backwards from the end of the file
+fn last_granule(reader: &mut BufReader<File>, serial: u32) -> Result<Option<u64>, DecoderError> {
+    let file_length = reader.seek(SeekFrom::End(0))?;
+    let mut window = 64 * 1024;
+
+    loop {
+        let start = file_length.saturating_sub(window);
+        let mut bytes = vec![0u8; (file_length - start) as usize];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut bytes)?;
+
+        // the last matching page wins
+        let granule = bytes
+            .windows(18)
+            .rev()
+            .filter(|header| &header[..4] == b"OggS" && u32::from_le_bytes([header[14], header[15], header[16], header[17]]) == serial)
+            .map(|header| u64::from_le_bytes([header[6], header[7], header[8], header[9], header[10], header[11], header[12], header[13]]))
+            .find(|granule| *granule != NO_GRANULE);
+
+        if granule.is_some() || start == 0 {
+            return Ok(granule);
+        }
+        window *= 4;
+    }
+}
+
+// the vendor string in front of the comments
+fn comment_vendor(comment: &[u8]) -> Option<String> {
+    let length = u32::from_le_bytes(comment.get(..4)?.try_into().ok()?) as usize;
+    let vendor = String::from_utf8_lossy(comment.get(4..4 + length)?).trim().to_string();
+
+    Some(vendor).filter(|vendor| !vendor.is_empty())
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = (index as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+fn crc_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, byte| (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::{Path, PathBuf};
+
+    use super::{crc_update, read_page, split_packets, OggDecoder, PAGE_CONTINUED, PAGE_FIRST, PAGE_LAST};
+    use crate::decoder::{Decoder, DecoderError};
+
+    const SERIAL: u32 = 0x1234;
+
+    fn page(flags: u8, granule: u64, segments: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = b"OggS\0".to_vec();
+        bytes.push(flags);
+        bytes.extend(granule.to_le_bytes());
+        bytes.extend(SERIAL.to_le_bytes());
+        bytes.extend([0; 8]);
+        bytes.push(segments.len() as u8);
+        bytes.extend(segments);
+        bytes.extend(body);
+
+        let crc = crc_update(0, &bytes);
+        bytes[22..26].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    fn packets_page(flags: u8, granule: u64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut segments = Vec::new();
+        for packet in packets {
+            segments.extend(std::iter::repeat_n(255, packet.len() / 255));
+            segments.push((packet.len() % 255) as u8);
+        }
+        page(flags, granule, &segments, &packets.concat())
+    }
+
+    // a stereo Opus stream with a 312 frame pre-skip, every packet is a lost 20 ms frame
+    fn opus_stream(end_granule: u64) -> Vec<u8> {
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend(312u16.to_le_bytes());
+        head.extend(48000u32.to_le_bytes());
+        head.extend([0, 0, 0]);
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend(12u32.to_le_bytes());
+        tags.extend(b"test encoder");
+        tags.extend(1u32.to_le_bytes());
+        tags.extend(10u32.to_le_bytes());
+        tags.extend(b"TITLE=Song");
+
+        let mut bytes = packets_page(PAGE_FIRST, 0, &[&head]);
+        bytes.extend(packets_page(0, 0, &[&tags]));
+        for index in 1..=5 {
+            let (flags, granule) = if index == 5 { (PAGE_LAST, end_granule) } else { (0, index * 3840) };
+            bytes.extend(packets_page(flags, granule, &[&[0xFC][..]; 4]));
+        }
+        bytes
+    }
+
+    fn write_ogg(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.ogg", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn open(path: &Path) -> Result<OggDecoder, DecoderError> {
+        let result = OggDecoder::open(path);
+        std::fs::remove_file(path).unwrap();
+        result
+    }
+
+    fn read_to_end(decoder: &mut OggDecoder) -> usize {
+        let mut samples = Vec::new();
+        while decoder.read_frames(&mut samples, 4096).unwrap() > 0 {}
+        samples.len() / 2
+    }
+
+    #[test]
+    fn computes_page_checksums() {
+        // CRC-32 with the 0x04C11DB7 polynomial, no reflection and no final xor
+        assert_eq!(crc_update(0, b"123456789"), 0x89A1897F);
+        assert_eq!(crc_update(crc_update(0, b"1234"), b"56789"), 0x89A1897F);
+    }
+
+    #[test]
+    fn reads_pages_and_splits_packets() {
+        let long_packet: Vec<u8> = (0..300).map(|index| index as u8).collect();
+        let mut corrupt = packets_page(0, 7, &[b"corrupt"]);
+        corrupt[30] ^= 0xFF;
+
+        let mut bytes = b"junk".to_vec();
+        bytes.extend(packets_page(PAGE_FIRST, 0, &[b"one", b"two"]));
+        bytes.extend(corrupt);
+        bytes.extend(page(0, u64::MAX, &[255], &long_packet[..255]));
+        bytes.extend(page(PAGE_CONTINUED, 300, &[45, 3], &[&long_packet[255..], &b"end"[..]].concat()));
+        bytes.extend(b"Ogg");
+
+        let path = write_ogg("ogg-pages", &bytes);
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let pages: Vec<_> = std::iter::from_fn(|| read_page(&mut reader).unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(pages.len(), 3);
+        assert_eq!((pages[0].flags, pages[0].granule, pages[0].serial), (PAGE_FIRST, 0, SERIAL));
+        assert_eq!((pages[1].granule, pages[2].granule), (u64::MAX, 300));
+
+        let mut partial_packet = Vec::new();
+        assert_eq!(split_packets(&pages[0], &mut partial_packet), [b"one".to_vec(), b"two".to_vec()]);
+        assert!(split_packets(&pages[1], &mut partial_packet).is_empty());
+        assert_eq!(split_packets(&pages[2], &mut partial_packet), [long_packet.clone(), b"end".to_vec()]);
+
+        // without the page it started on the continued packet is dropped
+        assert_eq!(split_packets(&pages[2], &mut Vec::new()), [b"end".to_vec()]);
+    }
+
+    #[test]
+    fn decodes_opus_stream() {
+        let mut decoder = open(&write_ogg("ogg-opus", &opus_stream(18700))).unwrap();
+        let info = decoder.info().clone();
+
+        assert_eq!((info.sample_rate, info.channels, info.channel_mask), (48000, 2, 0x3));
+        // the last page's granule position trims the end, the pre-skip the start
+        assert_eq!(info.frames, 18700 - 312);
+        assert_eq!(decoder.metadata().title.as_deref(), Some("Song"));
+        assert!(info.details.contains(&(String::from("Encoder"), String::from("test encoder"))));
+        assert_eq!(read_to_end(&mut decoder), 18700 - 312);
+
+        decoder.seek(15000).unwrap();
+        assert_eq!(read_to_end(&mut decoder), 18700 - 312 - 15000);
+        decoder.seek(100).unwrap();
+        assert_eq!(read_to_end(&mut decoder), 18700 - 312 - 100);
+    }
+
+    #[test]
+    fn rejects_malformed_streams() {
+        let result = open(&write_ogg("ogg-junk", &[0x4F; 1000]));
+        assert!(matches!(result, Err(DecoderError::Ogg("no Vorbis or Opus stream found"))));
+
+        // a broken checksum on the identification header hides the stream
+        let mut bytes = opus_stream(18700);
+        bytes[30] ^= 0xFF;
+        let result = open(&write_ogg("ogg-bad-crc", &bytes));
+        assert!(matches!(result, Err(DecoderError::Ogg("no Vorbis or Opus stream found"))));
+
+        // the stream is cut off behind the OpusHead page
+        let bytes = opus_stream(18700);
+        let result = open(&write_ogg("ogg-cut", &bytes[..27 + 1 + 19]));
+        assert!(matches!(result, Err(DecoderError::Ogg("stream ends inside the headers"))));
+    }
+}
@@ -0,0 +1,190 @@
+use std::ffi::CStr;
+use std::ptr::NonNull;
+
+use audiopus_sys::{
+    opus_multistream_decode_float, opus_multistream_decoder_create, opus_multistream_decoder_ctl,
+    opus_multistream_decoder_destroy, opus_strerror, OpusMSDecoder, OPUS_OK, OPUS_RESET_STATE
+};
+
+use crate::decoder::DecoderError;
+
+// Opus always decodes at 48 kHz, whatever rate the encoder was fed
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+// the longest packet Opus allows is 120 ms
+const MAX_PACKET_FRAMES: usize = 5760;
+
+// Decodes the audio packets of an Opus stream with libopus.
+// Every mapping family goes through the multistream decoder, plain mono and stereo streams are a single stream of it.
+pub struct OpusDecoder {
+    decoder: NonNull<OpusMSDecoder>,
+    channels: u16,
+    pre_skip: u64,
+    input_sample_rate: u32,
+    mapping_family: u8,
+    // the output gain from the header, as a factor
+    gain: f32,
+    pcm: Vec<f32>
+}
+
+// libopus keeps no thread affinity, the decoder is only ever used by whichever thread owns it
+unsafe impl Send for OpusDecoder {}
+
+impl OpusDecoder {
+    // `head_packet` is the OpusHead identification header
+    pub fn new(head_packet: &[u8]) -> Result<Self, DecoderError> {
+        if head_packet.len() < 19 || &head_packet[0..8] != b"OpusHead" {
+            return Err(DecoderError::Opus(String::from("invalid OpusHead header")));
+        }
+
+        let channels = head_packet[9];
+        let pre_skip = u16::from_le_bytes([head_packet[10], head_packet[11]]) as u64;
+        let input_sample_rate = u32::from_le_bytes([head_packet[12], head_packet[13], head_packet[14], head_packet[15]]);
+        let gain_db = i16::from_le_bytes([head_packet[16], head_packet[17]]) as f32 / 256.0;
+        let mapping_family = head_packet[18];
+
+        let (streams, coupled_streams, mapping) = if mapping_family == 0 {
+            if channels == 0 || channels > 2 {
+                return Err(DecoderError::Opus(String::from("invalid channel count")));
+            }
+            (1, channels - 1, vec![0, 1])
+        } else {
+            let Some(table) = head_packet.get(19..21 + channels as usize) else {
+                return Err(DecoderError::Opus(String::from("truncated channel mapping table")));
+            };
+            (table[0], table[1], table[2..].to_vec())
+        };
+
+        let mut error = 0;
+        let decoder = unsafe {
+            opus_multistream_decoder_create(
+                OPUS_SAMPLE_RATE as i32,
+                channels as i32,
+                streams as i32,
+                coupled_streams as i32,
+                mapping.as_ptr(),
+                &mut error
+            )
+        };
+        let Some(decoder) = NonNull::new(decoder).filter(|_| error == OPUS_OK) else {
+            return Err(opus_error(error));
+        };
+
+        Ok(OpusDecoder {
+            decoder,
+            channels: channels as u16,
+            pre_skip,
+            input_sample_rate,
+            mapping_family,
+            gain: 10f32.powf(gain_db / 20.0),
+            pcm: vec![0.0; MAX_PACKET_FRAMES * channels as usize]
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    // frames at the start of the stream that only prime the decoder
+    pub fn pre_skip(&self) -> u64 {
+        self.pre_skip
+    }
+
+    pub fn input_sample_rate(&self) -> u32 {
+        self.input_sample_rate
+    }
+
+    pub fn mapping_family(&self) -> u8 {
+        self.mapping_family
+    }
+
+    // appends the interleaved frames of one packet, in Vorbis channel order
+    pub fn decode_packet(&mut self, packet: &[u8], samples: &mut Vec<f32>) -> Result<usize, DecoderError> {
+        let frames = unsafe {
+            opus_multistream_decode_float(
+                self.decoder.as_ptr(),
+                packet.as_ptr(),
+                packet.len() as i32,
+                self.pcm.as_mut_ptr(),
+                MAX_PACKET_FRAMES as i32,
+                0
+            )
+        };
+        if frames < 0 {
+            return Err(opus_error(frames));
+        }
+
+        let end = frames as usize * self.channels as usize;
+        samples.extend(self.pcm[..end].iter().map(|sample| sample * self.gain));
+
+        Ok(frames as usize)
+    }
+
+    pub fn reset(&mut self) {
+        unsafe { opus_multistream_decoder_ctl(self.decoder.as_ptr(), OPUS_RESET_STATE) };
+    }
+}
+
+impl Drop for OpusDecoder {
+    fn drop(&mut self) {
+        unsafe { opus_multistream_decoder_destroy(self.decoder.as_ptr()) };
+    }
+}
+
+fn opus_error(code: i32) -> DecoderError {
+    let message = unsafe { CStr::from_ptr(opus_strerror(code)) };
+    DecoderError::Opus(message.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OpusDecoder;
+    use crate::decoder::DecoderError;
+
+    fn head(channels: u8, pre_skip: u16, gain: i16, mapping_family: u8, mapping: &[u8]) -> Vec<u8> {
+        let mut bytes = b"OpusHead\x01".to_vec();
+        bytes.push(channels);
+        bytes.extend(pre_skip.to_le_bytes());
+        bytes.extend(44100u32.to_le_bytes());
+        bytes.extend(gain.to_le_bytes());
+        bytes.push(mapping_family);
+        bytes.extend(mapping);
+        bytes
+    }
+
+    #[test]
+    fn reads_opus_head() {
+        let decoder = OpusDecoder::new(&head(2, 312, 0, 0, &[])).unwrap();
+        assert_eq!((decoder.channels(), decoder.pre_skip(), decoder.input_sample_rate(), decoder.mapping_family()), (2, 312, 44100, 0));
+        assert_eq!(decoder.gain, 1.0);
+
+        // 5.1 as four streams, two of them coupled, with a -6 dB output gain
+        let decoder = OpusDecoder::new(&head(6, 3840, -6 * 256, 1, &[4, 2, 0, 4, 1, 2, 3, 5])).unwrap();
+        assert_eq!((decoder.channels(), decoder.pre_skip(), decoder.mapping_family()), (6, 3840, 1));
+        assert!((decoder.gain - 0.501).abs() < 0.001);
+    }
+
+    #[test]
+    fn decodes_lost_frames_as_silence() {
+        let mut decoder = OpusDecoder::new(&head(2, 312, 0, 0, &[])).unwrap();
+
+        // a 20 ms CELT packet with no frame data is concealed
+        let mut samples = Vec::new();
+        assert_eq!(decoder.decode_packet(&[0xFC], &mut samples).unwrap(), 960);
+        assert_eq!(samples.len(), 1920);
+        assert!(samples.iter().all(|sample| sample.abs() < 1e-6));
+
+        // a code 3 packet holding no frames
+        assert!(matches!(decoder.decode_packet(&[0xFF, 0x00], &mut samples), Err(DecoderError::Opus(_))));
+    }
+
+    #[test]
+    fn rejects_malformed_heads() {
+        assert!(matches!(OpusDecoder::new(&head(2, 312, 0, 0, &[])[..18]), Err(DecoderError::Opus(_))));
+        assert!(matches!(OpusDecoder::new(b"OpusTags\x01\x02\0\0\0\0\0\0\0\0\0"), Err(DecoderError::Opus(_))));
+        assert!(matches!(OpusDecoder::new(&head(0, 312, 0, 0, &[])), Err(DecoderError::Opus(_))));
+        assert!(matches!(OpusDecoder::new(&head(3, 312, 0, 0, &[])), Err(DecoderError::Opus(_))));
+        assert!(matches!(OpusDecoder::new(&head(6, 312, 0, 1, &[4, 2, 0, 4])), Err(DecoderError::Opus(_))));
+        // a mapping to a stream that does not exist
+        assert!(matches!(OpusDecoder::new(&head(2, 312, 0, 1, &[1, 0, 0, 7])), Err(DecoderError::Opus(_))));
+    }
+}
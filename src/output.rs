@@ -1,34 +1,69 @@
 use std::sync::Arc;
-use cpal::{Device, OutputCallbackInfo, SampleRate, Stream, StreamConfig};
+use cpal::{BuildStreamError, Device, OutputCallbackInfo, Sample, SampleFormat, SampleRate, Stream, StreamConfig, StreamError};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use crossbeam_queue::SegQueue;
+use crossbeam_queue::ArrayQueue;
 use crate::{GuiToPlayerCommands, Player, PlayerToGuiCommands};
+use crate::dither::Dither;
+
+// devices that don't take f32 get the player's output converted through a buffer of this many frames, made up front
+const CONVERSION_FRAMES: usize = 4096;
 
 pub struct Output;
 
 impl Output {
-    pub fn new(from_gui_queue: Arc<SegQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<SegQueue<PlayerToGuiCommands>>) -> Stream {
+    // the gui needs the device's rate to build resampler kernels for it
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(from_gui_queue: Arc<ArrayQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<ArrayQueue<PlayerToGuiCommands>>) -> (Stream, u32) {
         let platform_settings = PlatformSettings::new();
-        let mut player = Player::new(from_gui_queue, to_gui_queue);
+        let config = &platform_settings.config;
+        let mut player = Player::new(from_gui_queue, to_gui_queue, config.sample_rate.0, config.channels as usize);
+        let device = &platform_settings.device;
 
-        let stream = platform_settings.device.build_output_stream(
-            &platform_settings.config,
-            move | data: &mut [f32], _: &OutputCallbackInfo | player.process(data),
-            move | err | {
-                eprintln!("{}", err);
-            }
-        ).unwrap();
+        let stream = match platform_settings.sample_format {
+            SampleFormat::F32 => device.build_output_stream(
+                config,
+                move | data: &mut [f32], _: &OutputCallbackInfo | player.process(data),
+                report_error
+            ),
+            SampleFormat::I16 => build_converted_stream(device, config, player, Dither::quantize_i16),
+            SampleFormat::U16 => build_converted_stream(device, config, player, Dither::quantize_u16)
+        }.unwrap();
 
         stream.play().unwrap();
 
-        stream
+        (stream, config.sample_rate.0)
     }
 }
 
+fn build_converted_stream<T: Sample + Send + 'static>(device: &Device, config: &StreamConfig, mut player: Player, convert: fn(&mut Dither, f32) -> T) -> Result<Stream, BuildStreamError> {
+    let mut buffer = vec![0.0f32; CONVERSION_FRAMES * config.channels as usize];
+    let mut dither = Dither::new();
+
+    device.build_output_stream(
+        config,
+        move | data: &mut [T], _: &OutputCallbackInfo | {
+            // both lengths are whole frames, so every chunk is too
+            for chunk in data.chunks_mut(buffer.len()) {
+                let buffer = &mut buffer[..chunk.len()];
+                player.process(buffer);
+                for (output, sample) in chunk.iter_mut().zip(buffer.iter()) {
+                    *output = convert(&mut dither, *sample);
+                }
+            }
+        },
+        report_error
+    )
+}
+
+fn report_error(err: StreamError) {
+    eprintln!("{}", err);
+}
+
 
 struct PlatformSettings {
     device: Device,
-    config: StreamConfig
+    config: StreamConfig,
+    sample_format: SampleFormat
 }
 
 impl PlatformSettings {
@@ -36,17 +71,32 @@ impl PlatformSettings {
         let host = cpal::default_host();
         let device = host.default_output_device().expect("No default output device was found");
 
-        let mut supported_configs_range = device.supported_output_configs().expect("error while querying configs");
-        let supported_config = supported_configs_range
-            .find(|d| d.max_sample_rate() == SampleRate(44100))
-            .expect("No config with correct sample rate found")
-            .with_max_sample_rate();
+        // the device keeps its own preferred rate whatever the files are, the player resamples to it
+        let default_config = device.default_output_config().expect("error while querying the default config");
+        let sample_rate = default_config.sample_rate();
+
+        // f32 is preferred, 16-bit formats are dithered down to by the output
+        let supported_config = device.supported_output_configs()
+            .expect("error while querying configs")
+            .max_by_key(|config| (
+                config.min_sample_rate() <= sample_rate && sample_rate <= config.max_sample_rate(),
+                config.channels() == default_config.channels(),
+                config.sample_format() == SampleFormat::F32,
+                config.sample_format() == SampleFormat::I16
+            ))
+            .map(|config| {
+                let rate = sample_rate.0.clamp(config.min_sample_rate().0, config.max_sample_rate().0);
+                config.with_sample_rate(SampleRate(rate))
+            })
+            .expect("No supported output config found");
 
+        let sample_format = supported_config.sample_format();
         let output_config = StreamConfig::from(supported_config);
 
         PlatformSettings {
             device,
             config: output_config,
+            sample_format
         }
     }
 }
\ No newline at end of file
@@ -1,24 +1,48 @@
 use std::sync::{Arc};
-use cpal::{Sample};
-use crossbeam_queue::SegQueue;
-use simple_bytes::{Bytes, BytesRead};
+use crossbeam_queue::ArrayQueue;
 use crate::{GuiToPlayerCommands, PlayerToGuiCommands};
+use crate::channel_map::ChannelMap;
+use crate::decoder::MAX_CHANNELS;
+use crate::markers::SampleLoop;
+use crate::resampler::Resampler;
+use crate::stream::DecoderStream;
 
 pub struct Player {
-    buffer_index: usize,
-    bytes_read: usize,
-    buffer: Option<Vec<u8>>,
+    frames_read: usize,
+    stream: Option<DecoderStream>,
+    block: Vec<f32>,
+    block_index: usize,
+    sample_rate: u32,
+    channels: u16,
+    device_channels: usize,
+    resampler: Resampler,
+    // one frame in the file's channel layout, between the resampler and the channel map
+    source_frame: Vec<f32>,
+    channel_map: ChannelMap,
+    sample_loop: Option<SampleLoop>,
+    loops_played: u32,
+    loop_mode: bool,
     playback_state: PlaybackState,
-    from_gui_queue: Arc<SegQueue<GuiToPlayerCommands>>,
-    to_gui_queue: Arc<SegQueue<PlayerToGuiCommands>>
+    from_gui_queue: Arc<ArrayQueue<GuiToPlayerCommands>>,
+    to_gui_queue: Arc<ArrayQueue<PlayerToGuiCommands>>
 }
 
 impl Player {
-    pub fn new(from_gui_queue: Arc<SegQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<SegQueue<PlayerToGuiCommands>>) -> Self {
+    pub fn new(from_gui_queue: Arc<ArrayQueue<GuiToPlayerCommands>>, to_gui_queue: Arc<ArrayQueue<PlayerToGuiCommands>>, device_sample_rate: u32, device_channels: usize) -> Self {
         Player {
-            buffer: None,
-            buffer_index: 0,
-            bytes_read: 0,
+            frames_read: 0,
+            stream: None,
+            block: Vec::new(),
+            block_index: 0,
+            sample_rate: device_sample_rate,
+            channels: 2,
+            device_channels,
+            resampler: Resampler::new(MAX_CHANNELS),
+            source_frame: vec![0.0; MAX_CHANNELS],
+            channel_map: ChannelMap::new(MAX_CHANNELS, device_channels),
+            sample_loop: None,
+            loops_played: 0,
+            loop_mode: false,
             playback_state: PlaybackState::Paused,
             from_gui_queue,
             to_gui_queue
@@ -29,42 +53,65 @@ impl Player {
         while let Some(command) = self.from_gui_queue.pop() {
             match command {
                 GuiToPlayerCommands::Play {
-                    buffer
+                    stream,
+                    sample_rate,
+                    channels,
+                    channel_mask,
+                    sample_loop,
+                    resampler_kernel
                 } => {
                     self.playback_state = PlaybackState::Playing;
-                    self.buffer = Some(buffer);
-                    self.buffer_index = 0;
-                    self.bytes_read = 0;
+                    self.retire_stream();
+                    self.stream = Some(stream);
+                    self.frames_read = 0;
+                    self.sample_rate = sample_rate;
+                    // decoder::open rejects wider tracks, the clamp keeps a stray one from indexing past the frame buffers
+                    debug_assert!(channels as usize <= MAX_CHANNELS, "{} channels, at most {} are supported", channels, MAX_CHANNELS);
+                    self.channels = channels.min(MAX_CHANNELS as u16);
+                    self.resampler.configure(resampler_kernel, self.channels as usize);
+                    self.channel_map.configure(self.channels as usize, channel_mask);
+                    self.sample_loop = sample_loop;
+                    self.loops_played = 0;
 
-                    self.to_gui_queue.push(PlayerToGuiCommands::Play);
+                    // the stream starts out without a loop, only the decoder thread's first blocks are dropped
+                    if self.stream_loop().is_some() {
+                        self.seek(0);
+                    }
+
+                    self.notify(PlayerToGuiCommands::Play);
                 },
                 GuiToPlayerCommands::Pause => {
                     self.playback_state = PlaybackState::Paused;
-                    self.to_gui_queue.push(PlayerToGuiCommands::Paused);
+                    self.notify(PlayerToGuiCommands::Paused);
                 },
                 GuiToPlayerCommands::PlayResume => {
                     self.playback_state = PlaybackState::Playing;
-                    self.to_gui_queue.push(PlayerToGuiCommands::Playing);
+                    self.notify(PlayerToGuiCommands::Playing);
                 },
                 GuiToPlayerCommands::Forward => {
-                    let sample_rate = 44100;
-                    let bytes_per_s = sample_rate * 4;
-                    let forwarded_amount = bytes_per_s * 15;
-                    self.bytes_read += forwarded_amount;
-                    self.buffer_index += forwarded_amount;
+                    let forwarded_amount = self.sample_rate as usize * 15;
+                    self.seek(self.frames_read + forwarded_amount);
                 }
                 GuiToPlayerCommands::Rewind => {
-                    let sample_rate = 44100;
-                    let bytes_per_s = sample_rate * 4;
-                    let forwarded_amount = bytes_per_s * 15;
-
-                    if self.bytes_read < forwarded_amount {
-                        self.bytes_read = 0;
-                        self.buffer_index = 0;
-                    } else {
-                        self.bytes_read -= forwarded_amount;
-                        self.buffer_index -= forwarded_amount;
-                    }
+                    let forwarded_amount = self.sample_rate as usize * 15;
+                    self.seek(self.frames_read.saturating_sub(forwarded_amount));
+                }
+                GuiToPlayerCommands::Seek {
+                    frame
+                } => {
+                    self.seek(frame);
+                }
+                GuiToPlayerCommands::LoopMode {
+                    enabled
+                } => {
+                    self.loop_mode = enabled;
+                    self.loops_played = 0;
+                    self.update_stream_loop();
+                }
+                GuiToPlayerCommands::ResamplerQuality {
+                    kernel
+                } => {
+                    self.resampler.set_kernel(kernel);
                 }
             }
         }
@@ -74,36 +121,138 @@ impl Player {
             return;
         }
 
-        if self.buffer.is_none() {
+        if self.stream.is_none() {
             silence(data);
             return;
         }
 
-        for sample in data.iter_mut() {
-            let buffer = &self.buffer.as_ref().unwrap();
-            if self.buffer_index + 1 > buffer.len() {
-                self.to_gui_queue.push(PlayerToGuiCommands::End);
-                self.buffer = None;
+        let channels = self.channels as usize;
+        let device_channels = self.device_channels;
+
+        for frame_index in 0..data.len() / device_channels {
+            let frame = &mut data[frame_index * device_channels..(frame_index + 1) * device_channels];
+            let stream = self.stream.as_ref().unwrap();
+            let block = &mut self.block;
+            let block_index = &mut self.block_index;
+            let frames_read = &mut self.frames_read;
+            let loops_played = &mut self.loops_played;
+            let mut ended = false;
+
+            let has_frame = self.resampler.process_frame(&mut self.source_frame[..channels], |input| {
+                if *block_index >= block.len() {
+                    let Some(decoded_block) = stream.next_block() else {
+                        return false;
+                    };
+
+                    if decoded_block.samples.is_empty() {
+                        stream.recycle(decoded_block.samples);
+                        ended = true;
+                        return false;
+                    }
+
+                    // the decoder thread jumped back from the loop end, blocks otherwise follow on from each other
+                    if decoded_block.first_frame != *frames_read {
+                        *loops_played += 1;
+                    }
+
+                    let played_block = std::mem::replace(block, decoded_block.samples);
+                    stream.recycle(played_block);
+                    *block_index = 0;
+                    *frames_read = decoded_block.first_frame;
+                }
+
+                input.copy_from_slice(&block[*block_index..*block_index + channels]);
+                *block_index += channels;
+                *frames_read += 1;
+
+                true
+            });
+
+            if ended {
+                self.notify(PlayerToGuiCommands::End);
+                self.retire_stream();
+                silence(&mut data[frame_index * device_channels..]);
                 return;
             }
-            let sample_bytes = &buffer[self.buffer_index..self.buffer_index + 2];
-            let mut bytes: Bytes = sample_bytes.into();
-            let sample_value = bytes.read_le_i16();
 
-            *sample = Sample::from(&sample_value);
+            // the decoder has not caught up yet, play silence instead of waiting for it
+            if !has_frame {
+                silence(frame);
+                continue;
+            }
 
-            self.bytes_read += 2;
-            self.buffer_index += 2;
+            self.channel_map.apply(&self.source_frame[..channels], frame);
+        }
 
-            let bytes_per_ms = 44100 * 4 / 1000;
-            if self.bytes_read % bytes_per_ms == 0 {
-                let milliseconds = (self.bytes_read / bytes_per_ms) as u128;
-                self.to_gui_queue.push(PlayerToGuiCommands::UpdateDuration {
-                    duration: milliseconds
-                })
-            }
+        self.report_position();
+    }
+
+    // the loop the decoder thread should still play, a play count of 0 loops for as long as loop mode stays on
+    fn stream_loop(&self) -> Option<SampleLoop> {
+        let sample_loop = self.sample_loop.filter(|_| self.loop_mode)?;
+
+        match sample_loop.play_count {
+            0 => Some(sample_loop),
+            play_count if self.loops_played < play_count => Some(SampleLoop { play_count: play_count - self.loops_played, ..sample_loop }),
+            _ => None
         }
     }
+
+    // restarts the decoder thread after the block being played with the current loop. the resampler keeps its history,
+    // and the rest of the block covers the time the decoder needs to catch up. switching loop mode on inside the
+    // block that holds the loop end can't wait for it, that jumps straight to the next frame instead
+    fn update_stream_loop(&mut self) {
+        let channels = self.channels as usize;
+        let block_end = self.frames_read + (self.block.len() - self.block_index) / channels;
+        let stream_loop = self.stream_loop();
+
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+
+        match stream_loop {
+            Some(sample_loop) if (self.frames_read..block_end).contains(&(sample_loop.end as usize)) => self.seek(self.frames_read),
+            _ => stream.seek(block_end, stream_loop)
+        }
+    }
+
+    // gives the sample buffer back before letting go of the stream, the decoder thread frees both
+    fn retire_stream(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            stream.recycle(std::mem::take(&mut self.block));
+        }
+        self.block_index = 0;
+    }
+
+    fn notify(&self, command: PlayerToGuiCommands) {
+        let _ = self.to_gui_queue.push(command);
+    }
+
+    // positions only take up half the queue so End and state changes always find room,
+    // a skipped position is overwritten by the next one anyway
+    fn report_position(&self) {
+        if self.to_gui_queue.len() < self.to_gui_queue.capacity() / 2 {
+            self.notify(PlayerToGuiCommands::UpdatePosition {
+                frame: self.frames_read as u64
+            });
+        }
+    }
+
+    // the decoder thread does the actual seeking, blocks decoded before the seek are dropped by the stream
+    fn seek(&mut self, frame: usize) {
+        let stream_loop = self.stream_loop();
+        if let Some(stream) = &mut self.stream {
+            stream.seek(frame, stream_loop);
+        }
+
+        self.frames_read = frame;
+        self.resampler.reset();
+
+        self.block.clear();
+        self.block_index = 0;
+
+        self.report_position();
+    }
 }
 
 pub fn silence(data: &mut [f32]) {
@@ -116,4 +265,211 @@ pub fn silence(data: &mut [f32]) {
 enum PlaybackState {
     Paused,
     Playing
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crossbeam_queue::ArrayQueue;
+
+    use super::Player;
+    use crate::{GuiToPlayerCommands, PlayerToGuiCommands};
+    use crate::markers::SampleLoop;
+    use crate::decoder::{self, StreamInfo};
+    use crate::resampler::{ResamplerKernels, ResamplerQuality};
+    use crate::stream::DecoderStream;
+
+    // counts every allocation, reallocation and free made on a thread while its guard is raised
+    struct AllocationGuard;
+
+    thread_local! {
+        static GUARDED: Cell<bool> = const { Cell::new(false) };
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        let _ = GUARDED.try_with(|guarded| {
+            if guarded.get() {
+                ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+            }
+        });
+    }
+
+    unsafe impl GlobalAlloc for AllocationGuard {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            count_allocation();
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: AllocationGuard = AllocationGuard;
+
+    fn guarded<F: FnOnce()>(callback: F) -> usize {
+        ALLOCATIONS.with(|allocations| allocations.set(0));
+        GUARDED.with(|guarded| guarded.set(true));
+        callback();
+        GUARDED.with(|guarded| guarded.set(false));
+        ALLOCATIONS.with(|allocations| allocations.get())
+    }
+
+    // gives the decoder thread time to queue a block, a callback never needs more than the one the player holds and the next
+    fn wait_for_decoder(player: &Player) {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while let Some(stream) = &player.stream {
+            if stream.queued_blocks() > 0 || Instant::now() > deadline {
+                break;
+            }
+            thread::yield_now();
+        }
+    }
+
+    // two seconds of a 16 bit stereo ramp at 44100 Hz
+    fn write_test_wav(name: &str) -> PathBuf {
+        let frames = 88200u32;
+        let data_size = frames * 4;
+
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        for frame in 0..frames {
+            let sample = (frame % 65536) as u16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        let path = std::env::temp_dir().join(format!("{}-{}.wav", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn play_command(path: &Path, sample_loop: Option<SampleLoop>, kernels: &mut ResamplerKernels) -> GuiToPlayerCommands {
+        let decoder = decoder::open(path).unwrap();
+        let StreamInfo { sample_rate, channels, channel_mask, .. } = *decoder.info();
+
+        GuiToPlayerCommands::Play {
+            stream: DecoderStream::new(decoder),
+            sample_rate,
+            channels,
+            channel_mask,
+            sample_loop,
+            resampler_kernel: kernels.get(sample_rate, ResamplerQuality::default())
+        }
+    }
+
+    #[test]
+    fn callback_does_not_allocate() {
+        let first_path = write_test_wav("player-guard-first");
+        let second_path = write_test_wav("player-guard-second");
+
+        let from_gui_queue = Arc::new(ArrayQueue::new(64));
+        let to_gui_queue = Arc::new(ArrayQueue::new(64));
+        let mut player = Player::new(from_gui_queue.clone(), to_gui_queue.clone(), 48000, 2);
+        let mut data = vec![0.0f32; 1024];
+        // the kernels outlive the player like the gui's do
+        let mut kernels = ResamplerKernels::new(48000);
+
+        let callbacks = |count: usize, player: &mut Player, data: &mut [f32]| {
+            let mut allocations = 0;
+            let mut ended = false;
+            for _ in 0..count {
+                wait_for_decoder(player);
+                allocations += guarded(|| player.process(data));
+                while let Some(command) = to_gui_queue.pop() {
+                    ended |= matches!(command, PlayerToGuiCommands::End);
+                }
+            }
+            (allocations, ended)
+        };
+
+        let mut allocations = 0;
+
+        from_gui_queue.push(play_command(&first_path, Some(SampleLoop { start: 4410, end: 8820, play_count: 2 }), &mut kernels)).ok().unwrap();
+        from_gui_queue.push(GuiToPlayerCommands::LoopMode { enabled: true }).ok().unwrap();
+        allocations += callbacks(50, &mut player, &mut data).0;
+
+        from_gui_queue.push(GuiToPlayerCommands::Seek { frame: 44100 }).ok().unwrap();
+        from_gui_queue.push(GuiToPlayerCommands::Pause).ok().unwrap();
+        allocations += callbacks(5, &mut player, &mut data).0;
+
+        from_gui_queue.push(GuiToPlayerCommands::PlayResume).ok().unwrap();
+        from_gui_queue.push(GuiToPlayerCommands::Forward).ok().unwrap();
+        from_gui_queue.push(GuiToPlayerCommands::Rewind).ok().unwrap();
+        from_gui_queue.push(GuiToPlayerCommands::ResamplerQuality { kernel: kernels.get(44100, ResamplerQuality::Best) }).ok().unwrap();
+        allocations += callbacks(20, &mut player, &mut data).0;
+
+        // switching tracks hands the first stream and its buffers back to the decoder thread
+        from_gui_queue.push(play_command(&second_path, None, &mut kernels)).ok().unwrap();
+        let mut ended = false;
+        for _ in 0..100 {
+            let (callback_allocations, callback_ended) = callbacks(10, &mut player, &mut data);
+            allocations += callback_allocations;
+            if callback_ended {
+                ended = true;
+                break;
+            }
+        }
+
+        std::fs::remove_file(first_path).unwrap();
+        std::fs::remove_file(second_path).unwrap();
+
+        assert!(ended, "the second track never reached its end");
+        assert_eq!(allocations, 0, "the audio callback allocated or freed memory");
+    }
+
+    #[test]
+    fn loops_run_on_without_gaps() {
+        let path = write_test_wav("player-loop");
+
+        let from_gui_queue = Arc::new(ArrayQueue::new(64));
+        let to_gui_queue = Arc::new(ArrayQueue::new(64));
+        // the file's own rate, so every output frame is an input frame
+        let mut player = Player::new(from_gui_queue.clone(), to_gui_queue.clone(), 44100, 2);
+        let mut data = vec![0.0f32; 1024];
+        let mut kernels = ResamplerKernels::new(44100);
+
+        from_gui_queue.push(GuiToPlayerCommands::LoopMode { enabled: true }).ok().unwrap();
+        from_gui_queue.push(play_command(&path, Some(SampleLoop { start: 1000, end: 4999, play_count: 2 }), &mut kernels)).ok().unwrap();
+        player.process(&mut data);
+
+        let mut frames: Vec<i64> = Vec::new();
+        while frames.len() < 20000 {
+            wait_for_decoder(&player);
+            player.process(&mut data);
+            frames.extend(data.chunks_exact(2).map(|frame| (frame[0] * 32768.0).round() as i64));
+        }
+
+        std::fs::remove_file(path).unwrap();
+
+        // the ramp's first frame is as silent as the ones before the decoder caught up
+        let first = frames.iter().position(|frame| *frame != 0).unwrap();
+        let expected: Vec<i64> = (1..5000).chain(1000..5000).chain(1000..5000).chain(5000..10000).collect();
+        assert_eq!(frames[first..first + expected.len()], expected[..]);
+    }
+}
@@ -1,66 +1,120 @@
 use std::fmt::{Display, Formatter};
 use std::fs::{read_dir};
 use std::path::{PathBuf};
-use crate::wav::Wav;
+use crate::decoder::{self, DecoderError, StreamInfo};
+use crate::frame_time::FrameTime;
+use crate::metadata::Metadata;
 
 pub struct Playlist {
     pub songs: Vec<Song>,
-    pub indexes: Vec<usize>
+    pub indexes: Vec<usize>,
+    pub skipped: Vec<SkippedSong>
 }
 
 impl Playlist {
     pub fn new() -> Self {
         let mut songs: Vec<Song> = Vec::new();
         let mut indexes: Vec<usize> = Vec::new();
+        let mut skipped: Vec<SkippedSong> = Vec::new();
 
         let song_paths: Vec<PathBuf> = read_dir("./playlist")
             .unwrap()
-            .filter(|res| res.as_ref().unwrap().file_name().to_str().unwrap().contains(".wav"))
             .map(|res| res.unwrap().path())
+            .filter(|path| decoder::is_supported(path))
             .collect();
 
-        let mut index_count: usize = 0;
         for path in song_paths {
-            let song = Song::from_path(path);
-            songs.push(song);
-
-            indexes.push(index_count);
-            index_count += 1;
+            match Song::from_path(path.clone()) {
+                Ok(song) => songs.push(song),
+                Err(error) => skipped.push(SkippedSong {
+                    path,
+                    error
+                })
+            }
         }
 
-        return Playlist {
+        songs.sort_by_cached_key(|song| song.sort_key());
+        indexes.extend(0..songs.len());
+
+        Playlist {
             songs,
-            indexes
+            indexes,
+            skipped
         }
     }
 }
 
 pub struct Song {
-    pub wav: Wav,
+    pub info: StreamInfo,
+    pub metadata: Metadata,
+    pub duration: FrameTime,
     pub artist: String,
     pub title: String,
+    pub album: Option<String>,
+    pub track: Option<u32>,
     pub path: PathBuf
 }
 
 impl Song {
-    pub fn from_path(path: PathBuf) -> Self {
-        let file_name = path.file_name().unwrap().to_str().unwrap();
-        let splitted_file_name: Vec<&str> = file_name.split("-").collect();
+    pub fn from_path(path: PathBuf) -> Result<Self, DecoderError> {
+        let decoder = decoder::open(&path)?;
+        let info = decoder.info().clone();
+        let metadata = decoder.metadata().clone();
+        let duration = FrameTime::new(info.frames, info.sample_rate);
 
-        let artist = String::from(*splitted_file_name.get(0).unwrap());
-        let title = String::from(*splitted_file_name.get(1).unwrap()).replace(".wav", "");
+        // tags win, "artist-title.wav" style file names are only a fallback
+        let file_stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        let (file_artist, file_title) = match file_stem.split_once('-') {
+            Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+            None => (None, file_stem.clone())
+        };
 
-        Song {
-            path: PathBuf::from(&path),
-            wav: Wav::new(path),
+        let artist = metadata.artist.clone()
+            .or(file_artist)
+            .unwrap_or_else(|| String::from("Unknown artist"));
+        let title = metadata.title.clone().unwrap_or(file_title);
+        let album = metadata.album.clone();
+        let track = metadata.track_number();
+
+        Ok(Song {
+            path,
+            info,
+            metadata,
+            duration,
             artist,
-            title
-        }
+            title,
+            album,
+            track
+        })
+    }
+
+    // songs are listed per artist, then album in track order
+    fn sort_key(&self) -> (String, String, u32, String) {
+        (
+            self.artist.to_lowercase(),
+            self.album.clone().unwrap_or_default().to_lowercase(),
+            self.track.unwrap_or(u32::MAX),
+            self.title.to_lowercase()
+        )
     }
 }
 
 impl Display for Song {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} - {}", self.title, self.artist)
+        match &self.album {
+            Some(album) => write!(f, "{} - {} ({})", self.title, self.artist, album),
+            None => write!(f, "{} - {}", self.title, self.artist)
+        }
+    }
+}
+
+pub struct SkippedSong {
+    pub path: PathBuf,
+    pub error: DecoderError
+}
+
+impl Display for SkippedSong {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
     }
 }
\ No newline at end of file
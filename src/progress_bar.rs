@@ -1,37 +1,71 @@
-use crate::playback_duration::PlaybackDuration;
+use crate::frame_time::FrameTime;
 use crate::terminal::Terminal;
-use crate::wav::WavDuration;
 
 pub struct ProgressBar {
-    max_ticks: f32
+    max_ticks: u64
 }
 
 impl ProgressBar {
     pub fn new() -> Self {
         ProgressBar {
-            max_ticks: 100.0
+            max_ticks: 100
         }
     }
 
-    pub fn update(&self, playback_duration: &PlaybackDuration, total_duration: WavDuration, terminal: &mut Terminal) {
-        terminal.write(&playback_duration);
+    // `markers` are frame positions, drawn as | ahead of the played ticks
+    pub fn update(&self, position: FrameTime, duration: FrameTime, markers: &[u64], terminal: &mut Terminal) {
+        let position_text = position.to_string();
+        let duration_text = duration.to_string();
+
+        terminal.write(&position_text);
         terminal.write(String::from("["));
         terminal.set_cursor_right(self.max_ticks as u16);
         terminal.write(String::from("]"));
-        terminal.write(&total_duration);
-        terminal.set_cursor_left((self.max_ticks + 6.0) as u16);
-
-        let ticks_per_second: f32 = self.max_ticks / total_duration.raw_seconds;
+        terminal.write(&duration_text);
+        terminal.set_cursor_left((self.max_ticks as usize + 1 + duration_text.len()) as u16);
 
-        let ms_per_tick: u128 = if ticks_per_second < 1.0 {
-            (1.0 / ticks_per_second) * 1000.0
-        } else {
-            ticks_per_second * 1000.0
-        } as u128;
-
-        let ticks = playback_duration.milliseconds / ms_per_tick;
+        let ticks = self.tick(position.frames, duration);
         for _ in 0..ticks {
             terminal.write(String::from("#"));
         }
+
+        let mut column = ticks;
+        for marker in markers {
+            let tick = self.tick(*marker, duration);
+            if tick < column || tick >= self.max_ticks {
+                continue;
+            }
+
+            if tick > column {
+                terminal.set_cursor_right((tick - column) as u16);
+            }
+            terminal.write(String::from("|"));
+            column = tick + 1;
+        }
+    }
+
+    // the tick a frame falls on, the last frame fills the whole bar
+    fn tick(&self, frame: u64, duration: FrameTime) -> u64 {
+        (duration.at(frame).fraction_of(duration) * self.max_ticks as f64) as u64
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProgressBar;
+    use crate::frame_time::FrameTime;
+
+    #[test]
+    fn places_frames_on_ticks() {
+        let progress_bar = ProgressBar::new();
+        let duration = FrameTime::new(44100 * 60, 44100);
+
+        assert_eq!(progress_bar.tick(0, duration), 0);
+        assert_eq!(progress_bar.tick(44100 * 30, duration), 50);
+        assert_eq!(progress_bar.tick(44100 * 60 - 1, duration), 99);
+        assert_eq!(progress_bar.tick(44100 * 60, duration), 100);
+        // past the end and on an empty track
+        assert_eq!(progress_bar.tick(44100 * 90, duration), 100);
+        assert_eq!(progress_bar.tick(1000, FrameTime::new(0, 0)), 0);
+    }
+}
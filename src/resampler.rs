@@ -0,0 +1,378 @@
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+// the kernel is tabulated at this many fractional positions between two input frames, positions in between
+// interpolate the two nearest phases
+const PHASES: usize = 256;
+// downsampling widens the kernel by the rate ratio, this caps it so the buffers can be sized up front
+const MAX_HALF_TAPS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResamplerQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+    Best
+}
+
+impl ResamplerQuality {
+    // zero crossings on each side of the kernel, Kaiser window beta and the passband edge as a fraction of the lower Nyquist rate
+    fn parameters(&self) -> (usize, f64, f64) {
+        match self {
+            ResamplerQuality::Low => (8, 6.0, 0.85),
+            ResamplerQuality::Medium => (16, 8.0, 0.9),
+            ResamplerQuality::High => (32, 10.0, 0.94),
+            ResamplerQuality::Best => (64, 12.0, 0.96)
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            ResamplerQuality::Low => ResamplerQuality::Medium,
+            ResamplerQuality::Medium => ResamplerQuality::High,
+            ResamplerQuality::High => ResamplerQuality::Best,
+            ResamplerQuality::Best => ResamplerQuality::Low
+        }
+    }
+}
+
+impl Display for ResamplerQuality {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResamplerQuality::Low => write!(f, "low"),
+            ResamplerQuality::Medium => write!(f, "medium"),
+            ResamplerQuality::High => write!(f, "high"),
+            ResamplerQuality::Best => write!(f, "best")
+        }
+    }
+}
+
+// The filter for one pair of rates at one quality. Tabulating it takes long enough to cause an underrun, so the gui
+// builds kernels and hands them to the player with the commands that need them.
+pub struct ResamplerKernel {
+    pub source_rate: u32,
+    pub target_rate: u32,
+    pub quality: ResamplerQuality,
+    half_taps: usize,
+    // PHASES + 1 rows of 2 * half_taps coefficients, each row normalised to unity gain
+    coefficients: Vec<f32>
+}
+
+impl ResamplerKernel {
+    // matching rates pass frames through, their kernel stays empty
+    pub fn new(source_rate: u32, target_rate: u32, quality: ResamplerQuality) -> Self {
+        let mut kernel = ResamplerKernel {
+            source_rate,
+            target_rate,
+            quality,
+            half_taps: 0,
+            coefficients: Vec::new()
+        };
+        if source_rate != target_rate {
+            kernel.build();
+        }
+
+        kernel
+    }
+
+    fn build(&mut self) {
+        let (zero_crossings, beta, passband) = self.quality.parameters();
+        let cutoff = (self.target_rate as f64 / self.source_rate as f64).min(1.0) * passband;
+
+        let half_taps = ((zero_crossings as f64 / cutoff).ceil() as usize).clamp(1, MAX_HALF_TAPS);
+        let window = 2 * half_taps;
+        let window_norm = bessel_i0(beta);
+        self.coefficients = vec![0.0; (PHASES + 1) * window];
+
+        for phase in 0..=PHASES {
+            let fraction = phase as f64 / PHASES as f64;
+            let row = &mut self.coefficients[phase * window..(phase + 1) * window];
+
+            // tap j holds the frame half_taps - 1 - j frames before the output's centre frame
+            let mut sum = 0.0;
+            for (tap, coefficient) in row.iter_mut().enumerate() {
+                let distance = fraction + half_taps as f64 - 1.0 - tap as f64;
+                let edge = distance / half_taps as f64;
+                let kaiser = if edge.abs() >= 1.0 {
+                    0.0
+                } else {
+                    bessel_i0(beta * (1.0 - edge * edge).sqrt()) / window_norm
+                };
+
+                let value = cutoff * sinc(cutoff * distance) * kaiser;
+                *coefficient = value as f32;
+                sum += value;
+            }
+
+            for coefficient in row.iter_mut() {
+                *coefficient = (*coefficient as f64 / sum) as f32;
+            }
+        }
+
+        self.half_taps = half_taps;
+    }
+}
+
+// Every kernel the gui built stays here for as long as it runs, so the player letting go of one never frees it
+// on the audio thread. There's one per source rate and quality that was played, which stays a handful.
+pub struct ResamplerKernels {
+    target_rate: u32,
+    kernels: Vec<Arc<ResamplerKernel>>
+}
+
+impl ResamplerKernels {
+    pub fn new(target_rate: u32) -> Self {
+        ResamplerKernels {
+            target_rate,
+            kernels: Vec::new()
+        }
+    }
+
+    pub fn get(&mut self, source_rate: u32, quality: ResamplerQuality) -> Arc<ResamplerKernel> {
+        let cached = self.kernels
+            .iter()
+            .find(|kernel| kernel.source_rate == source_rate && kernel.quality == quality);
+        if let Some(kernel) = cached {
+            return kernel.clone();
+        }
+
+        let kernel = Arc::new(ResamplerKernel::new(source_rate, self.target_rate, quality));
+        self.kernels.push(kernel.clone());
+        kernel
+    }
+}
+
+// Converts a stream of interleaved frames from the file's sample rate to the device's sample rate with a
+// Kaiser windowed sinc filter. Every output frame is the input frames around it weighted by the kernel
+// at the output's fractional position, the kernel's cutoff sits below the lower of the two Nyquist rates.
+// Matching rates pass frames through untouched. All buffers are sized for `max_channels` and the longest
+// kernel when the resampler is made, so reconfiguring it on the audio thread never allocates.
+pub struct Resampler {
+    // None until the first track, frames pass straight through without one
+    kernel: Option<Arc<ResamplerKernel>>,
+    max_channels: usize,
+    channels: usize,
+    step: f64,
+    // fractional position of the next output frame past the window's centre frame
+    position: f64,
+    // per channel, the last 2 * half_taps input frames stored twice in a row so the window is always one contiguous slice
+    history: Vec<f32>,
+    history_index: usize,
+    // input frames still missing before the next output frame can be made
+    needed_frames: usize,
+    frame: Vec<f32>
+}
+
+impl Resampler {
+    pub fn new(max_channels: usize) -> Self {
+        Resampler {
+            kernel: None,
+            max_channels,
+            channels: max_channels,
+            step: 1.0,
+            position: 0.0,
+            history: vec![0.0; max_channels * 4 * MAX_HALF_TAPS],
+            history_index: 0,
+            needed_frames: 0,
+            frame: vec![0.0; max_channels]
+        }
+    }
+
+    // switches to another source without reallocating, channels past the ones it was made for are dropped
+    pub fn configure(&mut self, kernel: Arc<ResamplerKernel>, channels: usize) {
+        debug_assert!(channels <= self.max_channels, "{} channels, the resampler was made for {}", channels, self.max_channels);
+
+        self.channels = channels.min(self.max_channels);
+        self.step = kernel.source_rate as f64 / kernel.target_rate as f64;
+        self.kernel = Some(kernel);
+        self.reset();
+    }
+
+    // swaps in a kernel of another quality, one made for other rates than the current source is ignored
+    pub fn set_kernel(&mut self, kernel: Arc<ResamplerKernel>) {
+        let same_rates = self.kernel
+            .as_ref()
+            .is_some_and(|current| current.source_rate == kernel.source_rate && current.target_rate == kernel.target_rate);
+
+        if same_rates {
+            self.kernel = Some(kernel);
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let half_taps = self.kernel.as_ref().map_or(0, |kernel| kernel.half_taps);
+        let window = 2 * half_taps;
+        for channel in 0..self.channels {
+            let start = channel * 4 * MAX_HALF_TAPS;
+            self.history[start..start + 2 * window].fill(0.0);
+        }
+
+        // the first output lines up with the first input frame, which needs the frames up to half a kernel ahead of it
+        self.position = 0.0;
+        self.history_index = 0;
+        self.needed_frames = half_taps + 1;
+    }
+
+    // writes one output frame, pulling input frames through `next_frame` as needed.
+    // returns false while `next_frame` has no input, calling it again later picks up where it stopped
+    pub fn process_frame<F: FnMut(&mut [f32]) -> bool>(&mut self, output: &mut [f32], mut next_frame: F) -> bool {
+        let kernel = match self.kernel.as_deref() {
+            Some(kernel) if kernel.source_rate != kernel.target_rate => kernel,
+            _ => return next_frame(output)
+        };
+
+        let channels = self.channels;
+        let window = 2 * kernel.half_taps;
+
+        while self.needed_frames > 0 {
+            if !next_frame(&mut self.frame[..channels]) {
+                return false;
+            }
+
+            self.history_index = (self.history_index + 1) % window;
+            for channel in 0..channels {
+                let start = channel * 4 * MAX_HALF_TAPS;
+                self.history[start + self.history_index] = self.frame[channel];
+                self.history[start + self.history_index + window] = self.frame[channel];
+            }
+            self.needed_frames -= 1;
+        }
+
+        let phase_position = self.position * PHASES as f64;
+        let phase = (phase_position as usize).min(PHASES - 1);
+        let blend = (phase_position - phase as f64) as f32;
+        let lower = &kernel.coefficients[phase * window..(phase + 1) * window];
+        let upper = &kernel.coefficients[(phase + 1) * window..(phase + 2) * window];
+
+        for (channel, sample) in output.iter_mut().enumerate().take(channels) {
+            // the oldest frame sits right after the newest one
+            let start = channel * 4 * MAX_HALF_TAPS + self.history_index + 1;
+            let frames = &self.history[start..start + window];
+
+            let mut lower_sum = 0.0;
+            let mut upper_sum = 0.0;
+            for ((frame, lower), upper) in frames.iter().zip(lower).zip(upper) {
+                lower_sum += frame * lower;
+                upper_sum += frame * upper;
+            }
+
+            *sample = lower_sum + (upper_sum - lower_sum) * blend;
+        }
+
+        self.position += self.step;
+        while self.position >= 1.0 {
+            self.position -= 1.0;
+            self.needed_frames += 1;
+        }
+
+        true
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// zeroth order modified Bessel function of the first kind, the series converges quickly for the betas used here
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+
+    for k in 1..50 {
+        term *= half_x / k as f64;
+        let squared = term * term;
+        sum += squared;
+        if squared < sum * 1e-12 {
+            break;
+        }
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Resampler, ResamplerKernel, ResamplerKernels, ResamplerQuality, MAX_HALF_TAPS};
+
+    // resamples `frames` stereo frames of `input`, returns the output frames
+    fn resample(resampler: &mut Resampler, frames: usize, input: impl Fn(usize) -> [f32; 2]) -> Vec<[f32; 2]> {
+        let mut output = Vec::new();
+        let mut frame = [0.0; 2];
+        let mut next = 0;
+        while resampler.process_frame(&mut frame, |input_frame| {
+            if next == frames {
+                return false;
+            }
+            input_frame.copy_from_slice(&input(next));
+            next += 1;
+            true
+        }) {
+            output.push(frame);
+        }
+        output
+    }
+
+    #[test]
+    fn passes_matching_rates_through() {
+        let kernel = ResamplerKernel::new(44100, 44100, ResamplerQuality::Best);
+        assert!(kernel.coefficients.is_empty());
+
+        let ramp = |frame: usize| [frame as f32, -(frame as f32)];
+        let mut resampler = Resampler::new(2);
+        assert_eq!(resample(&mut resampler, 100, ramp), (0..100).map(ramp).collect::<Vec<_>>());
+
+        resampler.configure(Arc::new(kernel), 2);
+        assert_eq!(resample(&mut resampler, 100, ramp), (0..100).map(ramp).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_dc_gain() {
+        for (source_rate, target_rate) in [(44100, 48000), (96000, 44100), (8000, 192000)] {
+            for quality in [ResamplerQuality::Low, ResamplerQuality::Best] {
+                let mut resampler = Resampler::new(2);
+                resampler.configure(Arc::new(ResamplerKernel::new(source_rate, target_rate, quality)), 2);
+
+                let output = resample(&mut resampler, 4000, |_| [0.5, -0.25]);
+                let expected = 4000 * target_rate as usize / source_rate as usize;
+                assert!(output.len().abs_diff(expected) <= MAX_HALF_TAPS * target_rate as usize / source_rate as usize + 1);
+
+                // once the kernel is filled with input a constant stays constant
+                for frame in &output[output.len() / 4..output.len() / 2] {
+                    assert!((frame[0] - 0.5).abs() < 1e-3 && (frame[1] + 0.25).abs() < 1e-3, "{:?} at {} -> {}", frame, source_rate, target_rate);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_kernels_for_other_rates() {
+        let mut kernels = ResamplerKernels::new(48000);
+        let high = kernels.get(44100, ResamplerQuality::High);
+        assert!(Arc::ptr_eq(&high, &kernels.get(44100, ResamplerQuality::High)));
+        assert_eq!((high.source_rate, high.target_rate, high.quality), (44100, 48000, ResamplerQuality::High));
+
+        let mut resampler = Resampler::new(2);
+        resampler.configure(high.clone(), 2);
+
+        resampler.set_kernel(kernels.get(96000, ResamplerQuality::Best));
+        assert!(Arc::ptr_eq(resampler.kernel.as_ref().unwrap(), &high));
+
+        let best = kernels.get(44100, ResamplerQuality::Best);
+        resampler.set_kernel(best.clone());
+        assert!(Arc::ptr_eq(resampler.kernel.as_ref().unwrap(), &best));
+
+        // heavy downsampling widens the kernel up to the buffers' size
+        assert_eq!(ResamplerKernel::new(192000, 8000, ResamplerQuality::Best).half_taps, MAX_HALF_TAPS);
+        assert_eq!(ResamplerQuality::Best.next(), ResamplerQuality::Low);
+    }
+}
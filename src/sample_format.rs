@@ -0,0 +1,180 @@
+use crate::wav::{FmtSubChunk, WAVE_FORMAT_ALAW, WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_MULAW, WAVE_FORMAT_PCM, WavError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+    U8,
+    I8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+    ALaw,
+    MuLaw
+}
+
+impl SampleFormat {
+    pub fn from_fmt(fmt: &FmtSubChunk) -> Result<Self, WavError> {
+        match (fmt.format_code(), fmt.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Ok(SampleFormat::U8),
+            (WAVE_FORMAT_PCM, 16) => Ok(SampleFormat::I16),
+            (WAVE_FORMAT_PCM, 24) => Ok(SampleFormat::I24),
+            (WAVE_FORMAT_PCM, 32) => Ok(SampleFormat::I32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Ok(SampleFormat::F32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Ok(SampleFormat::F64),
+            (WAVE_FORMAT_ALAW, 8) => Ok(SampleFormat::ALaw),
+            (WAVE_FORMAT_MULAW, 8) => Ok(SampleFormat::MuLaw),
+            (WAVE_FORMAT_PCM, bits)
+            | (WAVE_FORMAT_IEEE_FLOAT, bits)
+            | (WAVE_FORMAT_ALAW, bits)
+            | (WAVE_FORMAT_MULAW, bits) => Err(WavError::UnsupportedBitDepth(bits)),
+            (format, _) => Err(WavError::UnsupportedFormat(format))
+        }
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::I8 => 1,
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 => 4,
+            SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+            SampleFormat::ALaw => 1,
+            SampleFormat::MuLaw => 1
+        }
+    }
+
+    // converts a single little-endian sample into the -1.0..1.0 range cpal expects
+    pub fn decode(&self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+            SampleFormat::I8 => bytes[0] as i8 as f32 / 128.0,
+            SampleFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            SampleFormat::I24 => {
+                // shift into the top of an i32 so the sign bit is extended
+                let sample = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                sample as f32 / 8388608.0
+            }
+            SampleFormat::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648.0,
+            SampleFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            SampleFormat::F64 => f64::from_le_bytes(bytes[0..8].try_into().unwrap()) as f32,
+            SampleFormat::ALaw => alaw_to_linear(bytes[0]) as f32 / 32768.0,
+            SampleFormat::MuLaw => mulaw_to_linear(bytes[0]) as f32 / 32768.0
+        }
+    }
+}
+
+// G.711 A-law expansion to 16-bit linear PCM
+pub fn alaw_to_linear(alaw: u8) -> i16 {
+    let alaw = alaw ^ 0x55;
+    let exponent = (alaw & 0x70) >> 4;
+    let mantissa = (alaw & 0x0F) as i16;
+
+    let magnitude = if exponent == 0 {
+        (mantissa << 4) + 8
+    } else {
+        ((mantissa << 4) + 0x108) << (exponent - 1)
+    };
+
+    if alaw & 0x80 != 0 {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+// G.711 mu-law expansion to 16-bit linear PCM
+pub fn mulaw_to_linear(mulaw: u8) -> i16 {
+    let mulaw = !mulaw;
+    let exponent = (mulaw & 0x70) >> 4;
+    let mantissa = (mulaw & 0x0F) as i16;
+
+    let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+
+    if mulaw & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alaw_to_linear, mulaw_to_linear, SampleFormat};
+    use crate::wav::{FmtSubChunk, WavError, WAVE_FORMAT_ALAW, WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_MULAW, WAVE_FORMAT_PCM};
+
+    fn fmt(format_code: u16, bits_per_sample: u16) -> FmtSubChunk {
+        FmtSubChunk {
+            chunk_id: String::from("fmt "),
+            chunk_size: 16,
+            audio_format: format_code,
+            channels: 1,
+            sample_rate: 8000,
+            byte_rate: 8000 * bits_per_sample as u32 / 8,
+            block_align: bits_per_sample / 8,
+            bits_per_sample,
+            extensible: None,
+            extra: Vec::new()
+        }
+    }
+
+    #[test]
+    fn picks_pcm_formats_by_bit_depth() {
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_PCM, 8)).unwrap(), SampleFormat::U8);
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_PCM, 16)).unwrap(), SampleFormat::I16);
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_PCM, 24)).unwrap(), SampleFormat::I24);
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_PCM, 32)).unwrap(), SampleFormat::I32);
+    }
+
+    #[test]
+    fn decodes_pcm_samples() {
+        assert_eq!(SampleFormat::U8.decode(&[0x00]), -1.0);
+        assert_eq!(SampleFormat::U8.decode(&[0x80]), 0.0);
+        assert_eq!(SampleFormat::I16.decode(&[0x00, 0x80]), -1.0);
+        assert_eq!(SampleFormat::I16.decode(&[0x00, 0x40]), 0.5);
+        // the sign of a 24-bit sample sits in its third byte
+        assert_eq!(SampleFormat::I24.decode(&[0x00, 0x00, 0xC0]), -0.5);
+        assert_eq!(SampleFormat::I24.decode(&[0x00, 0x00, 0x40]), 0.5);
+        assert_eq!(SampleFormat::I32.decode(&[0x00, 0x00, 0x00, 0x80]), -1.0);
+        assert_eq!(SampleFormat::I32.decode(&[0x00, 0x00, 0x00, 0x20]), 0.25);
+    }
+
+    #[test]
+    fn decodes_float_samples() {
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_IEEE_FLOAT, 32)).unwrap(), SampleFormat::F32);
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_IEEE_FLOAT, 64)).unwrap(), SampleFormat::F64);
+        assert_eq!(SampleFormat::F32.decode(&(-0.75f32).to_le_bytes()), -0.75);
+        assert_eq!(SampleFormat::F64.decode(&0.125f64.to_le_bytes()), 0.125);
+        // float samples aren't clipped, the player handles anything past full scale
+        assert_eq!(SampleFormat::F32.decode(&1.5f32.to_le_bytes()), 1.5);
+    }
+
+    #[test]
+    fn rejects_unsupported_float() {
+        assert!(matches!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_IEEE_FLOAT, 16)), Err(WavError::UnsupportedBitDepth(16))));
+    }
+
+    // reference values from the G.711 tables
+    #[test]
+    fn expands_g711() {
+        assert_eq!([0xD5, 0x55, 0xAA, 0x2A].map(alaw_to_linear), [8, -8, 32256, -32256]);
+        assert_eq!([0xFF, 0x7F, 0x80, 0x00].map(mulaw_to_linear), [0, 0, 32124, -32124]);
+        assert_eq!(SampleFormat::ALaw.decode(&[0xAA]), 32256.0 / 32768.0);
+        assert_eq!(SampleFormat::MuLaw.decode(&[0x00]), -32124.0 / 32768.0);
+    }
+
+    #[test]
+    fn rejects_wide_g711() {
+        assert_eq!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_ALAW, 8)).unwrap(), SampleFormat::ALaw);
+        assert!(matches!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_ALAW, 16)), Err(WavError::UnsupportedBitDepth(16))));
+        assert!(matches!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_MULAW, 16)), Err(WavError::UnsupportedBitDepth(16))));
+    }
+
+    #[test]
+    fn rejects_unsupported_pcm() {
+        assert!(matches!(SampleFormat::from_fmt(&fmt(WAVE_FORMAT_PCM, 12)), Err(WavError::UnsupportedBitDepth(12))));
+        assert!(matches!(SampleFormat::from_fmt(&fmt(0x0055, 16)), Err(WavError::UnsupportedFormat(0x0055))));
+    }
+}
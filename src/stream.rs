@@ -0,0 +1,315 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::decoder::Decoder;
+use crate::markers::SampleLoop;
+
+// how many decoded blocks may wait for the player, together with FRAMES_PER_BLOCK this bounds the memory a stream uses
+const QUEUE_CAPACITY: usize = 16;
+// a new sample buffer is only made while none is waiting to be reused, so at most every queued block, the one being
+// decoded, the one the player holds and a dropped stale one exist at once
+const RECYCLE_CAPACITY: usize = QUEUE_CAPACITY + 4;
+const FRAMES_PER_BLOCK: usize = 4096;
+const IDLE_SLEEP: Duration = Duration::from_millis(2);
+
+pub struct DecodedBlock {
+    pub generation: u64,
+    pub first_frame: usize,
+    // an empty block marks the end of the data chunk
+    pub samples: Vec<f32>
+}
+
+struct StreamShared {
+    blocks: ArrayQueue<DecodedBlock>,
+    recycled: ArrayQueue<Vec<f32>>,
+    seeks: ArrayQueue<(u64, usize, Option<SampleLoop>)>
+}
+
+// Runs a decoder on a background thread, the player drains the decoded blocks through a
+// bounded lock-free queue. Every seek bumps the generation so blocks decoded before it can be told apart.
+// Sample buffers travel back through `recycled` and the decoder thread holds the last reference to the
+// shared queues, so neither using nor dropping a stream frees memory on the audio thread.
+// Loops are played by the decoder thread, it jumps back at the loop end so the blocks run on without a gap.
+pub struct DecoderStream {
+    shared: Arc<StreamShared>,
+    generation: u64
+}
+
+impl DecoderStream {
+    pub fn new(decoder: Box<dyn Decoder>) -> Self {
+        let shared = Arc::new(StreamShared {
+            blocks: ArrayQueue::new(QUEUE_CAPACITY),
+            recycled: ArrayQueue::new(RECYCLE_CAPACITY),
+            seeks: ArrayQueue::new(1)
+        });
+
+        let decoder = StreamDecoder {
+            shared: shared.clone(),
+            decoder,
+            generation: 0,
+            next_frame: 0,
+            sample_loop: None,
+            ended: false
+        };
+
+        thread::spawn(move || decoder.run());
+
+        DecoderStream {
+            shared,
+            generation: 0
+        }
+    }
+
+    // only the latest seek matters, an older one still waiting for the decoder is replaced.
+    // the loop's play count is how many more times to jump back, 0 keeps looping
+    pub fn seek(&mut self, frame: usize, sample_loop: Option<SampleLoop>) {
+        self.generation += 1;
+        self.shared.seeks.force_push((self.generation, frame, sample_loop));
+    }
+
+    // the next block decoded since the last seek, None while the decoder has not caught up yet
+    pub fn next_block(&self) -> Option<DecodedBlock> {
+        while let Some(block) = self.shared.blocks.pop() {
+            if block.generation == self.generation {
+                return Some(block);
+            }
+            self.recycle(block.samples);
+        }
+
+        None
+    }
+
+    // blocks waiting for the player, stale ones from before a seek included
+    #[cfg(test)]
+    pub fn queued_blocks(&self) -> usize {
+        self.shared.blocks.len()
+    }
+
+    // hands a played sample buffer back to the decoder thread instead of freeing it
+    pub fn recycle(&self, samples: Vec<f32>) {
+        if samples.capacity() > 0 {
+            let _ = self.shared.recycled.push(samples);
+        }
+    }
+}
+
+struct StreamDecoder {
+    shared: Arc<StreamShared>,
+    decoder: Box<dyn Decoder>,
+    generation: u64,
+    next_frame: usize,
+    sample_loop: Option<SampleLoop>,
+    ended: bool
+}
+
+impl StreamDecoder {
+    fn run(mut self) {
+        let mut pending: Option<DecodedBlock> = None;
+
+        // the player dropped its handle once this thread holds the only reference
+        while Arc::strong_count(&self.shared) > 1 {
+            if let Some((generation, frame, sample_loop)) = self.shared.seeks.pop() {
+                pending = None;
+                self.generation = generation;
+                self.next_frame = frame;
+                self.sample_loop = sample_loop;
+                self.ended = self.decoder.seek(frame as u64).is_err();
+            }
+
+            if pending.is_none() && !self.ended {
+                pending = Some(self.decode_next());
+            }
+
+            match pending.take() {
+                Some(block) => {
+                    if let Err(block) = self.shared.blocks.push(block) {
+                        pending = Some(block);
+                        thread::sleep(IDLE_SLEEP);
+                    }
+                }
+                None => thread::sleep(IDLE_SLEEP)
+            }
+        }
+    }
+
+    // reads up to FRAMES_PER_BLOCK frames, or the end marker once the decoder ran out of frames.
+    // a block stops at the loop end, the next one starts over at the loop start.
+    // a decoding error ends the track where it happened
+    fn decode_next(&mut self) -> DecodedBlock {
+        let mut samples = self.shared.recycled.pop().unwrap_or_default();
+        samples.clear();
+
+        let loop_end = self.sample_loop
+            .map(|sample_loop| sample_loop.end as usize)
+            .filter(|loop_end| self.next_frame <= *loop_end);
+        let max_frames = loop_end.map_or(FRAMES_PER_BLOCK, |loop_end| FRAMES_PER_BLOCK.min(loop_end + 1 - self.next_frame));
+
+        let frames = self.decoder.read_frames(&mut samples, max_frames).unwrap_or_else(|_| {
+            samples.clear();
+            0
+        });
+
+        if frames == 0 {
+            self.ended = true;
+        }
+
+        let block = DecodedBlock {
+            generation: self.generation,
+            first_frame: self.next_frame,
+            samples
+        };
+        self.next_frame += frames;
+
+        if let (Some(sample_loop), Some(loop_end)) = (self.sample_loop, loop_end) {
+            if frames > 0 && self.next_frame == loop_end + 1 {
+                self.loop_back(sample_loop);
+            }
+        }
+
+        block
+    }
+
+    fn loop_back(&mut self, sample_loop: SampleLoop) {
+        self.sample_loop = match sample_loop.play_count {
+            0 => Some(sample_loop),
+            1 => None,
+            play_count => Some(SampleLoop { play_count: play_count - 1, ..sample_loop })
+        };
+
+        self.next_frame = sample_loop.start as usize;
+        self.ended = self.decoder.seek(sample_loop.start).is_err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{DecodedBlock, DecoderStream, FRAMES_PER_BLOCK};
+    use crate::decoder::{Decoder, DecoderError, StreamInfo};
+    use crate::markers::SampleLoop;
+    use crate::metadata::Metadata;
+
+    // a mono track whose samples are their own frame numbers, optionally failing once it reaches `error_at`
+    struct CountingDecoder {
+        info: StreamInfo,
+        metadata: Metadata,
+        position: u64,
+        error_at: Option<u64>
+    }
+
+    impl CountingDecoder {
+        fn new(frames: u64, error_at: Option<u64>) -> Box<Self> {
+            Box::new(CountingDecoder {
+                info: StreamInfo {
+                    sample_rate: 44100,
+                    channels: 1,
+                    channel_mask: 0,
+                    frames,
+                    markers: Vec::new(),
+                    loops: Vec::new(),
+                    time_reference: None,
+                    timecode_rate: None,
+                    details: Vec::new()
+                },
+                metadata: Metadata::default(),
+                position: 0,
+                error_at
+            })
+        }
+    }
+
+    impl Decoder for CountingDecoder {
+        fn open(_path: &Path) -> Result<Self, DecoderError> {
+            Err(DecoderError::UnsupportedFile)
+        }
+
+        fn info(&self) -> &StreamInfo {
+            &self.info
+        }
+
+        fn metadata(&self) -> &Metadata {
+            &self.metadata
+        }
+
+        fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError> {
+            if self.error_at.is_some_and(|error_at| self.position >= error_at) {
+                return Err(DecoderError::Mp3("broken frame"));
+            }
+
+            let end = (self.position + max_frames as u64).min(self.info.frames);
+            let end = self.error_at.map_or(end, |error_at| end.min(error_at));
+            samples.extend((self.position..end).map(|frame| frame as f32));
+            let frames = (end - self.position) as usize;
+            self.position = end;
+
+            Ok(frames)
+        }
+
+        fn seek(&mut self, frame: u64) -> Result<(), DecoderError> {
+            self.position = frame.min(self.info.frames);
+            Ok(())
+        }
+    }
+
+    fn next_block(stream: &DecoderStream) -> DecodedBlock {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        loop {
+            if let Some(block) = stream.next_block() {
+                return block;
+            }
+            assert!(Instant::now() < deadline, "the decoder thread sent no block");
+            thread::yield_now();
+        }
+    }
+
+    // (first frame, frame count) of every block up to the end marker
+    fn blocks(stream: &DecoderStream) -> Vec<(usize, usize)> {
+        let mut blocks = Vec::new();
+        loop {
+            let block = next_block(stream);
+            if block.samples.is_empty() {
+                return blocks;
+            }
+            assert_eq!(block.samples[0], block.first_frame as f32);
+            blocks.push((block.first_frame, block.samples.len()));
+        }
+    }
+
+    #[test]
+    fn streams_blocks_in_order() {
+        let stream = DecoderStream::new(CountingDecoder::new(10000, None));
+
+        assert_eq!(blocks(&stream), [(0, FRAMES_PER_BLOCK), (4096, FRAMES_PER_BLOCK), (8192, 1808)]);
+    }
+
+    #[test]
+    fn drops_blocks_from_before_a_seek() {
+        let mut stream = DecoderStream::new(CountingDecoder::new(10000, None));
+        next_block(&stream);
+        stream.seek(9000, None);
+
+        assert_eq!(blocks(&stream), [(9000, 1000)]);
+    }
+
+    #[test]
+    fn loops_on_the_decoder_thread() {
+        let mut stream = DecoderStream::new(CountingDecoder::new(10000, None));
+        stream.seek(0, Some(SampleLoop { start: 1000, end: 4999, play_count: 2 }));
+
+        // blocks stop at the loop end while there are jumps left, the last pass runs on through it
+        assert_eq!(blocks(&stream), [(0, 4096), (4096, 904), (1000, 4000), (1000, 4096), (5096, 4096), (9192, 808)]);
+    }
+
+    #[test]
+    fn decoding_errors_end_the_track() {
+        let stream = DecoderStream::new(CountingDecoder::new(10000, Some(5000)));
+
+        assert_eq!(blocks(&stream), [(0, 4096), (4096, 904)]);
+    }
+}
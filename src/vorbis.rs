@@ -0,0 +1,138 @@
+use lewton::audio::{read_audio_packet_generic, PreviousWindowRight};
+use lewton::header::{read_header_ident, read_header_setup, IdentHeader, SetupHeader};
+use lewton::samples::InterleavedSamples;
+
+use crate::decoder::DecoderError;
+
+// Decodes the audio packets of a Vorbis stream, lewton does the actual work.
+// The packets come from the Ogg demuxer, which also takes care of the comment header.
+pub struct VorbisDecoder {
+    ident: IdentHeader,
+    setup: SetupHeader,
+    previous_window: PreviousWindowRight
+}
+
+impl VorbisDecoder {
+    pub fn new(ident_packet: &[u8], setup_packet: &[u8]) -> Result<Self, DecoderError> {
+        let ident = read_header_ident(ident_packet).map_err(|err| DecoderError::Vorbis(err.into()))?;
+        let setup = read_header_setup(setup_packet, ident.audio_channels, (ident.blocksize_0, ident.blocksize_1))
+            .map_err(|err| DecoderError::Vorbis(err.into()))?;
+
+        Ok(VorbisDecoder {
+            ident,
+            setup,
+            previous_window: PreviousWindowRight::new()
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.ident.audio_sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.ident.audio_channels as u16
+    }
+
+    pub fn nominal_bitrate(&self) -> i32 {
+        self.ident.bitrate_nominal
+    }
+
+    // appends the interleaved frames of one packet, in Vorbis channel order.
+    // the first packet after a reset only primes the overlap and adds nothing
+    pub fn decode_packet(&mut self, packet: &[u8], samples: &mut Vec<f32>) -> Result<usize, DecoderError> {
+        let decoded: InterleavedSamples<f32> = read_audio_packet_generic(&self.ident, &self.setup, packet, &mut self.previous_window)
+            .map_err(|err| DecoderError::Vorbis(err.into()))?;
+
+        samples.extend_from_slice(&decoded.samples);
+
+        Ok(decoded.samples.len() / decoded.channel_count.max(1))
+    }
+
+    pub fn reset(&mut self) {
+        self.previous_window = PreviousWindowRight::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VorbisDecoder;
+    use crate::decoder::DecoderError;
+
+    // packs (value, bit count) fields least significant bit first, as Vorbis does
+    fn bits(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut bit_count = 0;
+        for (value, width) in fields {
+            for bit in 0..*width {
+                if bit_count % 8 == 0 {
+                    bytes.push(0);
+                }
+                *bytes.last_mut().unwrap() |= (((value >> bit) & 1) as u8) << (bit_count % 8);
+                bit_count += 1;
+            }
+        }
+        bytes
+    }
+
+    // 256 and 2048 sample blocks
+    fn ident(channels: u8, sample_rate: u32) -> Vec<u8> {
+        let mut bytes = b"\x01vorbis".to_vec();
+        bytes.extend(0u32.to_le_bytes());
+        bytes.push(channels);
+        bytes.extend(sample_rate.to_le_bytes());
+        for bitrate in [0i32, 128000, 0] {
+            bytes.extend(bitrate.to_le_bytes());
+        }
+        bytes.extend([0xB8, 1]);
+        bytes
+    }
+
+    // the smallest setup there is: one two entry codebook, a floor 1 without partitions, an empty residue and a single short block mode
+    fn setup() -> Vec<u8> {
+        let mut bytes = b"\x05vorbis".to_vec();
+        bytes.extend(bits(&[
+            // codebook count, sync, dimensions, entries, unordered, not sparse, two one bit lengths, no lookup
+            (0, 8), (0x564342, 24), (1, 16), (2, 24), (0, 1), (0, 1), (0, 5), (0, 5), (0, 4),
+            // one time domain transform
+            (0, 6), (0, 16),
+            // floor 1 with no partitions, multiplier 1, 8 range bits
+            (0, 6), (1, 16), (0, 5), (0, 2), (8, 4),
+            // residue 0 covering nothing, one classification from codebook 0
+            (0, 6), (0, 16), (0, 24), (0, 24), (0, 24), (0, 6), (0, 8), (0, 3), (0, 1),
+            // mapping 0, one submap, no coupling
+            (0, 6), (0, 16), (0, 1), (0, 1), (0, 2), (0, 8), (0, 8), (0, 8),
+            // mode with short blocks, then the framing bit
+            (0, 6), (0, 1), (0, 16), (0, 16), (0, 8), (1, 1)
+        ]));
+        bytes
+    }
+
+    #[test]
+    fn decodes_silent_packets() {
+        let mut decoder = VorbisDecoder::new(&ident(2, 44100), &setup()).unwrap();
+        assert_eq!((decoder.sample_rate(), decoder.channels(), decoder.nominal_bitrate()), (44100, 2, 128000));
+
+        // an audio packet whose floors are all unused, the first one only primes the overlap
+        let mut samples = Vec::new();
+        assert_eq!(decoder.decode_packet(&[0x00], &mut samples).unwrap(), 0);
+        assert_eq!(decoder.decode_packet(&[0x00], &mut samples).unwrap(), 128);
+        assert_eq!(samples, vec![0.0; 256]);
+
+        decoder.reset();
+        assert_eq!(decoder.decode_packet(&[0x00], &mut samples).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert!(matches!(VorbisDecoder::new(&ident(2, 0), &setup()), Err(DecoderError::Vorbis(_))));
+        assert!(matches!(VorbisDecoder::new(&ident(2, 44100)[..20], &setup()), Err(DecoderError::Vorbis(_))));
+        assert!(matches!(VorbisDecoder::new(&ident(2, 44100), &setup()[..12]), Err(DecoderError::Vorbis(_))));
+
+        // the setup header in place of the identification header
+        assert!(matches!(VorbisDecoder::new(&setup(), &ident(2, 44100)), Err(DecoderError::Vorbis(_))));
+
+        // a header packet where audio is expected
+        let mut decoder = VorbisDecoder::new(&ident(1, 44100), &setup()).unwrap();
+        assert!(decoder.decode_packet(&ident(1, 44100), &mut Vec::new()).is_err());
+    }
+}
@@ -1,205 +1,1109 @@
+use std::collections::HashMap;
+use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
 use std::path::Path;
 
+use crate::bwf::{BextChunk, IxmlChunk, Timecode};
+use crate::codec::Codec;
+use crate::decoder::{Decoder, DecoderError, StreamInfo};
+use crate::id3::read_id3v2;
+use crate::markers::{Marker, read_cue_points, read_labels, read_sample_loops, SampleLoop};
+use crate::metadata::Metadata;
+use crate::sample_format::SampleFormat;
+
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub const WAVE_FORMAT_ADPCM: u16 = 0x0002;
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub const WAVE_FORMAT_ALAW: u16 = 0x0006;
+pub const WAVE_FORMAT_MULAW: u16 = 0x0007;
+pub const WAVE_FORMAT_IMA_ADPCM: u16 = 0x0011;
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// every KSDATAFORMAT_SUBTYPE GUID shares these trailing 14 bytes, the first two hold the format code
+pub const SUBTYPE_GUID_SUFFIX: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71];
+
+// Wave64 identifies chunks by GUID, the standard ones start with the equivalent RIFF fourcc
+const W64_RIFF_GUID: [u8; 16] = [0x72, 0x69, 0x66, 0x66, 0x2E, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00];
+const W64_WAVE_GUID: [u8; 16] = [0x77, 0x61, 0x76, 0x65, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A];
+const W64_CHUNK_GUID_SUFFIX: [u8; 12] = [0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A];
+const W64_LIST_GUID_SUFFIX: [u8; 12] = [0x2F, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00];
+
+pub const SPEAKER_FRONT_LEFT: u32 = 0x1;
+pub const SPEAKER_FRONT_RIGHT: u32 = 0x2;
+pub const SPEAKER_FRONT_CENTER: u32 = 0x4;
+pub const SPEAKER_LOW_FREQUENCY: u32 = 0x8;
+pub const SPEAKER_BACK_LEFT: u32 = 0x10;
+pub const SPEAKER_BACK_RIGHT: u32 = 0x20;
+pub const SPEAKER_FRONT_LEFT_OF_CENTER: u32 = 0x40;
+pub const SPEAKER_FRONT_RIGHT_OF_CENTER: u32 = 0x80;
+pub const SPEAKER_BACK_CENTER: u32 = 0x100;
+pub const SPEAKER_SIDE_LEFT: u32 = 0x200;
+pub const SPEAKER_SIDE_RIGHT: u32 = 0x400;
+pub const SPEAKER_TOP_CENTER: u32 = 0x800;
+pub const SPEAKER_TOP_FRONT_LEFT: u32 = 0x1000;
+pub const SPEAKER_TOP_FRONT_CENTER: u32 = 0x2000;
+pub const SPEAKER_TOP_FRONT_RIGHT: u32 = 0x4000;
+pub const SPEAKER_TOP_BACK_LEFT: u32 = 0x8000;
+pub const SPEAKER_TOP_BACK_CENTER: u32 = 0x10000;
+pub const SPEAKER_TOP_BACK_RIGHT: u32 = 0x20000;
+
+#[allow(dead_code)]
 pub struct Wav {
     pub header: WavHeader,
-    pub duration: WavDuration
+    pub codec: Codec,
+    pub channel_mask: u32,
+    pub metadata: Metadata,
+    pub markers: Vec<Marker>,
+    pub loops: Vec<SampleLoop>
 }
 
 impl Wav {
-    pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        let file = File::open(path).expect("Unable to open WAV file");
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, WavError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
         let mut reader = BufReader::new(file);
 
-        let mut header_bytes = vec![0u8; 44];
-        reader.read_exact(&mut header_bytes).expect("Error when reading header");
+        let header = WavHeader::from_reader(&mut reader)?;
+
+        if header.data.offset.checked_add(header.data.chunk_size).is_none_or(|end| end > file_size) {
+            return Err(WavError::Truncated);
+        }
+
+        let codec = Codec::from_fmt(&header.fmt, header.riff.container.endianness())?;
+        let channel_mask = header.fmt.channel_mask();
+        // ID3 tags tend to be more complete than INFO, INFO only fills in what ID3 lacks
+        let metadata = header.id3.clone().unwrap_or_default().or(header.info.clone().unwrap_or_default());
 
-        let header = WavHeader::from_header_bytes(header_bytes);
-        let duration = WavDuration::from_header(&header);
+        let mut markers: Vec<Marker> = header.cue_points
+            .iter()
+            .map(|cue_point| Marker {
+                name: header.labels.get(&cue_point.id).cloned(),
+                ..cue_point.clone()
+            })
+            .collect();
+        markers.sort_by_key(|marker| marker.position);
 
-        Wav {
+        let loops = header.sample_loops.clone();
+
+        Ok(Wav {
             header,
-            duration
-        }
+            codec,
+            channel_mask,
+            metadata,
+            markers,
+            loops
+        })
     }
 }
 
-pub struct WavDuration {
-    pub raw_seconds: f32,
-    pub seconds: f32,
-    pub raw_minutes: f32,
-    pub minutes: f32,
-    pub milliseconds: f32,
+// Decoder backend for RIFF, RIFX, RF64 and Wave64 files
+pub struct WavDecoder {
+    wav: Wav,
+    info: StreamInfo,
+    file: File,
+    bytes: Vec<u8>,
+    codec_block: Vec<f32>,
+    // decoded samples not handed out yet, codec blocks rarely line up with the frames asked for
+    decoded: Vec<f32>,
+    decoded_index: usize,
+    // read position relative to the start of the data chunk
+    position: u64,
+    next_frame: u64,
+    skipped_frames: usize
 }
 
-impl WavDuration {
-    pub fn from_header(header: &WavHeader) -> Self {
-        let samples: f32 = (header.data.chunk_size / ((header.fmt.channels * header.fmt.bits_per_sample / 8) as u32)) as f32;
+impl Decoder for WavDecoder {
+    fn open(path: &Path) -> Result<Self, DecoderError> {
+        let wav = Wav::new(path)?;
+
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(wav.header.data.offset))?;
+
+        // the fact chunk holds the real sample count per channel, prefer it over deriving it from the data size
+        let frames = match &wav.header.fact {
+            Some(fact) => fact.sample_length,
+            None => wav.codec.frame_count(wav.header.data.chunk_size)
+        };
 
-        let raw_seconds: f32 = samples / (header.fmt.sample_rate as f32);
-        let raw_minutes: f32 = raw_seconds / 60.0;
+        let info = StreamInfo {
+            sample_rate: wav.header.fmt.sample_rate,
+            channels: wav.header.fmt.channels,
+            channel_mask: wav.channel_mask,
+            frames,
+            markers: wav.markers.clone(),
+            loops: wav.loops.clone(),
+            time_reference: wav.header.bext.as_ref().map(|bext| bext.time_reference),
+            timecode_rate: wav.header.ixml.as_ref().and_then(|ixml| ixml.timecode_rate),
+            details: details(&wav.header)
+        };
 
-        let minutes = raw_minutes.floor();
+        Ok(WavDecoder {
+            wav,
+            info,
+            file,
+            bytes: Vec::new(),
+            codec_block: Vec::new(),
+            decoded: Vec::new(),
+            decoded_index: 0,
+            position: 0,
+            next_frame: 0,
+            skipped_frames: 0
+        })
+    }
 
-        let remaining_seconds = raw_minutes % minutes;
-        let seconds = (remaining_seconds * 60.0).ceil();
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
 
-        WavDuration {
-            raw_seconds,
-            seconds,
-            raw_minutes,
-            minutes,
-            milliseconds: seconds * 1000.0
+    fn metadata(&self) -> &Metadata {
+        &self.wav.metadata
+    }
+
+    fn read_frames(&mut self, samples: &mut Vec<f32>, max_frames: usize) -> Result<usize, DecoderError> {
+        let channels = self.wav.codec.channels().max(1);
+
+        // frames past the fact sample count are padding of the last block
+        let max_frames = max_frames.min(self.info.frames.saturating_sub(self.next_frame) as usize);
+        if max_frames == 0 {
+            return Ok(0);
+        }
+
+        if self.decoded.len() - self.decoded_index < channels {
+            self.decode_blocks(max_frames)?;
         }
+
+        let frames = ((self.decoded.len() - self.decoded_index) / channels).min(max_frames);
+        let end = self.decoded_index + frames * channels;
+        samples.extend_from_slice(&self.decoded[self.decoded_index..end]);
+        self.decoded_index = end;
+        self.next_frame += frames as u64;
+
+        Ok(frames)
+    }
+
+    // positions the file at the block holding `frame`, the frames before it are dropped after decoding
+    fn seek(&mut self, frame: u64) -> Result<(), DecoderError> {
+        let frames_per_block = self.wav.codec.frames_per_block() as u64;
+        let block_number = frame / frames_per_block;
+
+        self.position = block_number * self.wav.codec.block_align() as u64;
+        self.next_frame = frame;
+        self.skipped_frames = (frame % frames_per_block) as usize;
+        self.decoded.clear();
+        self.decoded_index = 0;
+
+        self.file.seek(SeekFrom::Start(self.wav.header.data.offset + self.position))?;
+
+        Ok(())
     }
 }
 
-impl Display for WavDuration {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let minutes = if self.minutes < 10.0 {
-            format!("0{}", self.minutes)
-        } else {
-            format!("{}", self.minutes)
-        };
+impl WavDecoder {
+    // decodes enough whole codec blocks for `frames` frames into `decoded`
+    fn decode_blocks(&mut self, frames: usize) -> Result<(), DecoderError> {
+        let codec = &self.wav.codec;
+        let block_align = codec.block_align();
 
-        let seconds = if self.seconds < 10.0 {
-            format!("0{}", self.seconds)
-        } else {
-            format!("{}", self.seconds)
-        };
+        let blocks = (frames + self.skipped_frames).div_ceil(codec.frames_per_block());
+        let remaining = self.wav.header.data.chunk_size.saturating_sub(self.position);
+        let read_size = ((blocks * block_align) as u64).min(remaining) as usize;
+
+        self.bytes.resize(read_size, 0);
+        self.file.read_exact(&mut self.bytes)?;
+        self.position += read_size as u64;
+
+        self.decoded.clear();
+        for block in self.bytes.chunks(block_align) {
+            codec.decode_block(block, &mut self.codec_block);
+            self.decoded.extend_from_slice(&self.codec_block);
+        }
+
+        self.decoded_index = (self.skipped_frames * codec.channels()).min(self.decoded.len());
+        self.skipped_frames = 0;
+
+        Ok(())
+    }
+}
+
+// bext and iXML fields for the track info view
+fn details(header: &WavHeader) -> Vec<(String, String)> {
+    let mut lines: Vec<(String, String)> = Vec::new();
+
+    let mut push = |label: &str, value: String| {
+        if !value.trim().is_empty() {
+            lines.push((String::from(label), value));
+        }
+    };
+
+    if let Some(bext) = &header.bext {
+        push("Description", bext.description.clone());
+        push("Originator", bext.originator.clone());
+        push("Originator reference", bext.originator_reference.clone());
+        push("Origination", format!("{} {}", bext.origination_date, bext.origination_time));
+
+        let frame_rate = header.ixml.as_ref().and_then(|ixml| ixml.timecode_rate);
+        let time_reference = Timecode::from_samples(bext.time_reference, header.fmt.sample_rate, frame_rate);
+        push("Time reference", format!("{} ({} samples)", time_reference, bext.time_reference));
+
+        let loudness = [
+            ("Loudness", bext.loudness_value, "LUFS"),
+            ("Loudness range", bext.loudness_range, "LU"),
+            ("Max true peak", bext.max_true_peak_level, "dBTP"),
+            ("Max momentary", bext.max_momentary_loudness, "LUFS"),
+            ("Max short term", bext.max_short_term_loudness, "LUFS")
+        ];
+        for (label, value, unit) in loudness {
+            if let Some(value) = value {
+                push(label, format!("{:.1} {}", value, unit));
+            }
+        }
+
+        push("Coding history", bext.coding_history.lines().next().unwrap_or_default().to_string());
+    }
 
-        write!(f, "{}:{}", minutes, seconds)
+    if let Some(ixml) = &header.ixml {
+        let fields = [
+            ("Project", &ixml.project),
+            ("Scene", &ixml.scene),
+            ("Take", &ixml.take),
+            ("Tape", &ixml.tape),
+            ("Note", &ixml.note)
+        ];
+        for (label, value) in fields {
+            if let Some(value) = value {
+                push(label, value.clone());
+            }
+        }
     }
+
+    lines
+}
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(std::io::Error),
+    InvalidMagic,
+    UnknownContainer(String),
+    MissingFmtChunk,
+    MissingDataChunk,
+    UnsupportedFormat(u16),
+    UnsupportedBitDepth(u16),
+    UnsupportedChannelCount(u16),
+    UnsupportedSampleFormat(SampleFormat),
+    InvalidSampleRate,
+    MarkerOutOfRange(u64),
+    Truncated
 }
 
-impl Clone for WavDuration {
-    fn clone(&self) -> Self {
-        WavDuration {
-            raw_seconds: self.raw_seconds,
-            raw_minutes: self.raw_minutes,
-            seconds: self.seconds,
-            minutes: self.minutes,
-            milliseconds: self.milliseconds
+impl Display for WavError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::Io(err) => write!(f, "I/O error: {}", err),
+            WavError::InvalidMagic => write!(f, "not a WAVE file"),
+            WavError::UnknownContainer(id) => write!(f, "unknown container id {:?}", id),
+            WavError::MissingFmtChunk => write!(f, "no fmt chunk found"),
+            WavError::MissingDataChunk => write!(f, "no data chunk found"),
+            WavError::UnsupportedFormat(format) => write!(f, "unsupported audio format 0x{:04X}", format),
+            WavError::UnsupportedBitDepth(bits) => write!(f, "unsupported bit depth {}", bits),
+            WavError::UnsupportedChannelCount(channels) => write!(f, "unsupported channel count {}", channels),
+            WavError::UnsupportedSampleFormat(sample_format) => write!(f, "can't write {:?} samples to a WAVE file", sample_format),
+            WavError::InvalidSampleRate => write!(f, "invalid sample rate"),
+            WavError::MarkerOutOfRange(position) => write!(f, "marker position {} doesn't fit a cue point", position),
+            WavError::Truncated => write!(f, "file is truncated")
         }
     }
 }
 
-impl Copy for WavDuration {}
+impl Error for WavError {}
+
+impl From<std::io::Error> for WavError {
+    fn from(err: std::io::Error) -> Self {
+        if err.kind() == ErrorKind::UnexpectedEof {
+            WavError::Truncated
+        } else {
+            WavError::Io(err)
+        }
+    }
+}
 
+#[allow(dead_code)]
 pub struct WavHeader {
     pub riff: RiffChunk,
     pub fmt: FmtSubChunk,
     pub data: DataSubChunk,
+    pub fact: Option<FactSubChunk>,
+    pub ds64: Option<Ds64SubChunk>,
+    pub info: Option<Metadata>,
+    pub id3: Option<Metadata>,
+    pub bext: Option<BextChunk>,
+    pub ixml: Option<IxmlChunk>,
+    pub cue_points: Vec<Marker>,
+    pub labels: HashMap<u32, String>,
+    pub sample_loops: Vec<SampleLoop>,
+    pub chunks: Vec<Chunk>
 }
 
 impl WavHeader {
-    pub fn from_header_bytes(header: Vec<u8>) -> Self {
-        let riff_chunk = RiffChunk::from_header_bytes(&header);
-        let fmt_sub_chunk = FmtSubChunk::from_header_bytes(&header);
-        let data_sub_chunk = DataSubChunk::from_header_bytes(&header);
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self, WavError> {
+        // chunk sizes come from the file, bodies are only read once they're known to fit in it
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let riff = RiffChunk::from_reader(reader)?;
+        let container = riff.container;
+        let endianness = container.endianness();
+
+        let mut riff_end = riff.end_offset();
+        let mut offset: u64 = riff.header_size;
+
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut fmt: Option<FmtSubChunk> = None;
+        let mut data: Option<DataSubChunk> = None;
+        let mut fact: Option<FactSubChunk> = None;
+        let mut ds64: Option<Ds64SubChunk> = None;
+        let mut info: Option<Metadata> = None;
+        let mut id3: Option<Metadata> = None;
+        let mut bext: Option<BextChunk> = None;
+        let mut ixml: Option<IxmlChunk> = None;
+        let mut cue_points: Vec<Marker> = Vec::new();
+        let mut labels: HashMap<u32, String> = HashMap::new();
+        let mut sample_loops: Vec<SampleLoop> = Vec::new();
 
-        WavHeader {
-            riff: riff_chunk,
-            fmt: fmt_sub_chunk,
-            data: data_sub_chunk
+        while offset.saturating_add(container.chunk_header_size()) <= riff_end.min(stream_end) {
+            let mut chunk = match Chunk::from_reader(reader, container, offset) {
+                Ok(chunk) => chunk,
+                Err(_) => break
+            };
+
+            // RF64 chunks too large for their 32-bit size field defer to the ds64 chunk
+            if let Some(ds64) = &ds64 {
+                if chunk.size == u32::MAX as u64 {
+                    chunk.size = ds64.chunk_size(&chunk.id).unwrap_or(chunk.size);
+                }
+            }
+
+            match chunk.id.as_str() {
+                "ds64" if container == Container::Rf64 => {
+                    let ds64_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    let ds64_chunk = Ds64SubChunk::from_chunk_bytes(&ds64_bytes)?;
+                    riff_end = ds64_chunk.riff_size.saturating_add(8);
+                    ds64 = Some(ds64_chunk);
+                }
+                "fmt " => {
+                    let fmt_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    fmt = Some(FmtSubChunk::from_chunk_bytes(&chunk, &fmt_bytes, endianness)?);
+                }
+                "data" => {
+                    data = Some(DataSubChunk::from_chunk(&chunk));
+                }
+                "fact" => {
+                    let fact_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    fact = Some(FactSubChunk::from_chunk_bytes(&fact_bytes, endianness)?);
+                }
+                "LIST" => {
+                    let list_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    if list_bytes.starts_with(b"INFO") {
+                        info = Some(Metadata::from_info_list(&list_bytes[4..], endianness));
+                    } else if list_bytes.starts_with(b"adtl") {
+                        labels.extend(read_labels(&list_bytes[4..], endianness));
+                    }
+                }
+                "id3 " | "ID3 " => {
+                    let id3_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    id3 = read_id3v2(&id3_bytes);
+                }
+                "bext" => {
+                    let bext_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    bext = BextChunk::from_chunk_bytes(&bext_bytes);
+                }
+                "iXML" => {
+                    let ixml_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    ixml = Some(IxmlChunk::from_chunk_bytes(&ixml_bytes));
+                }
+                "cue " => {
+                    let cue_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    cue_points = read_cue_points(&cue_bytes, endianness);
+                }
+                "smpl" => {
+                    let smpl_bytes = read_chunk_body(reader, &chunk, stream_end)?;
+                    sample_loops = read_sample_loops(&smpl_bytes, endianness);
+                }
+                _ => {}
+            }
+
+            offset = chunk.next_offset(container);
+            chunks.push(chunk);
+
+            reader.seek(SeekFrom::Start(offset.min(stream_end)))?;
+        }
+
+        let fmt = fmt.ok_or(WavError::MissingFmtChunk)?;
+        let data = data.ok_or(WavError::MissingDataChunk)?;
+
+        if let (Some(fact), Some(ds64)) = (&mut fact, &ds64) {
+            if fact.sample_length == u32::MAX as u64 {
+                fact.sample_length = ds64.sample_count;
+            }
+        }
+
+        Ok(WavHeader {
+            riff,
+            fmt,
+            data,
+            fact,
+            ds64,
+            info,
+            id3,
+            bext,
+            ixml,
+            cue_points,
+            labels,
+            sample_loops,
+            chunks
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    Riff,
+    Rifx,
+    Rf64,
+    Wave64
+}
+
+impl Container {
+    pub fn endianness(&self) -> Endianness {
+        match self {
+            Container::Rifx => Endianness::Big,
+            _ => Endianness::Little
+        }
+    }
+
+    pub fn chunk_header_size(&self) -> u64 {
+        match self {
+            Container::Wave64 => 24,
+            _ => 8
+        }
+    }
+
+    pub fn alignment(&self) -> u64 {
+        match self {
+            Container::Wave64 => 8,
+            _ => 2
         }
     }
 }
 
+#[derive(Debug)]
+pub struct Chunk {
+    pub id: String,
+    pub offset: u64,
+    pub size: u64
+}
+
+impl Chunk {
+    // offset points at the first byte of the chunk header, the stored offset at the first byte of the body
+    pub fn from_reader<R: Read>(reader: &mut R, container: Container, offset: u64) -> Result<Self, WavError> {
+        if container == Container::Wave64 {
+            let mut chunk_header = [0u8; 24];
+            reader.read_exact(&mut chunk_header)?;
+
+            // Wave64 sizes include the 24-byte GUID/size header
+            let size = le_u64(&chunk_header, 16).saturating_sub(24);
+
+            return Ok(Chunk {
+                id: w64_chunk_id(&chunk_header[0..16]),
+                offset: offset + 24,
+                size
+            });
+        }
+
+        let mut chunk_header = [0u8; 8];
+        reader.read_exact(&mut chunk_header)?;
+
+        Ok(Chunk {
+            id: four_cc(&chunk_header[0..4]),
+            offset: offset + 8,
+            size: container.endianness().u32(&chunk_header, 4) as u64
+        })
+    }
+
+    // RIFF chunks are word aligned so odd sized chunks are followed by a pad byte, Wave64 chunks are 8-byte aligned
+    pub fn next_offset(&self, container: Container) -> u64 {
+        let alignment = container.alignment();
+        let padding = (alignment - self.size % alignment) % alignment;
+
+        self.offset.saturating_add(self.size).saturating_add(padding)
+    }
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct RiffChunk {
     chunk_id: String,
-    chunk_size: u32,
-    format: String
+    chunk_size: u64,
+    format: String,
+    pub container: Container,
+    pub header_size: u64
 }
 
 impl RiffChunk {
-    pub fn from_header_bytes(wav_header: &Vec<u8>) -> Self {
-        let chunk_id_bytes = &wav_header[0..4];
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, WavError> {
+        let mut wav_header = [0u8; 12];
+        reader.read_exact(&mut wav_header)?;
+
+        let chunk_id = four_cc(&wav_header[0..4]);
+
+        if chunk_id == "riff" {
+            let mut w64_header = [0u8; 40];
+            w64_header[0..12].copy_from_slice(&wav_header);
+            reader.read_exact(&mut w64_header[12..40])?;
+
+            if w64_header[0..16] != W64_RIFF_GUID || w64_header[24..40] != W64_WAVE_GUID {
+                return Err(WavError::InvalidMagic);
+            }
 
-        let chunk_size_bytes = &wav_header[4..8];
-        let chunk_size_bytes_arr: [u8; 4] = chunk_size_bytes.try_into().expect("Incorrect amount of bytes");
+            return Ok(RiffChunk {
+                chunk_id,
+                chunk_size: le_u64(&w64_header, 16),
+                format: String::from("wave"),
+                container: Container::Wave64,
+                header_size: 40
+            });
+        }
+
+        let container = match chunk_id.as_str() {
+            "RIFF" => Container::Riff,
+            "RIFX" => Container::Rifx,
+            "RF64" | "BW64" => Container::Rf64,
+            _ => return Err(WavError::UnknownContainer(chunk_id))
+        };
 
-        let format_bytes = &wav_header[8..12];
+        let chunk_size = container.endianness().u32(&wav_header, 4) as u64;
+        let format = four_cc(&wav_header[8..12]);
 
-        let chunk_id: String = chunk_id_bytes.iter().map(|byte| *byte as char).collect();
-        let chunk_size = u32::from_le_bytes(chunk_size_bytes_arr);
-        let format: String = format_bytes.iter().map(|byte| *byte as char).collect();
+        if format != "WAVE" {
+            return Err(WavError::InvalidMagic);
+        }
 
-        RiffChunk {
+        Ok(RiffChunk {
             chunk_id,
             chunk_size,
-            format
+            format,
+            container,
+            header_size: 12
+        })
+    }
+
+    // offset of the first byte past the RIFF chunk, RF64 files only know it once ds64 has been read
+    pub fn end_offset(&self) -> u64 {
+        match self.container {
+            Container::Wave64 => self.chunk_size,
+            Container::Rf64 if self.chunk_size == u32::MAX as u64 => u64::MAX,
+            _ => self.chunk_size + 8
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct Ds64SubChunk {
+    pub riff_size: u64,
+    pub data_size: u64,
+    pub sample_count: u64,
+    pub table: Vec<(String, u64)>
+}
+
+impl Ds64SubChunk {
+    pub fn from_chunk_bytes(ds64_bytes: &[u8]) -> Result<Self, WavError> {
+        if ds64_bytes.len() < 28 {
+            return Err(WavError::Truncated);
+        }
+
+        let table_length = le_u32(ds64_bytes, 24) as usize;
+        let table = ds64_bytes[28..]
+            .chunks_exact(12)
+            .take(table_length)
+            .map(|entry| (four_cc(&entry[0..4]), le_u64(entry, 4)))
+            .collect();
+
+        Ok(Ds64SubChunk {
+            riff_size: le_u64(ds64_bytes, 0),
+            data_size: le_u64(ds64_bytes, 8),
+            sample_count: le_u64(ds64_bytes, 16),
+            table
+        })
+    }
+
+    pub fn chunk_size(&self, chunk_id: &str) -> Option<u64> {
+        if chunk_id == "data" {
+            return Some(self.data_size);
         }
+
+        self.table
+            .iter()
+            .find(|(id, _)| id == chunk_id)
+            .map(|(_, size)| *size)
     }
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct FmtSubChunk {
     pub chunk_id: String,
-    pub chunk_size: u32,
+    pub chunk_size: u64,
     pub audio_format: u16,
     pub channels: u16,
     pub sample_rate: u32,
     pub byte_rate: u32,
     pub block_align: u16,
-    pub bits_per_sample: u16
+    pub bits_per_sample: u16,
+    pub extensible: Option<FmtExtensible>,
+    pub extra: Vec<u8>
 }
 
 impl FmtSubChunk {
-    pub fn from_header_bytes(wav_header: &Vec<u8>) -> Self {
-        let chunk_id_bytes = &wav_header[12..16];
-        let chunk_size_bytes: [u8; 4] = (&wav_header[16..20]).try_into().expect("Incorrect amount of bytes");
-        let audio_format_bytes: [u8; 2] = (&wav_header[20..22]).try_into().expect("Incorrect amount of bytes");
-        let channels_bytes: [u8; 2] = (&wav_header[22..24]).try_into().expect("Incorrect amount of bytes");
-        let sample_rate_bytes: [u8; 4] = (&wav_header[24..28]).try_into().expect("Incorrect amount of bytes");
-        let byte_rate_bytes: [u8; 4] = (&wav_header[28..32]).try_into().expect("Incorrect amount of bytes");
-        let block_align_bytes: [u8; 2] = (&wav_header[32..34]).try_into().expect("Incorrect amount of bytes");
-        let bits_per_sample_bytes: [u8; 2] = (&wav_header[34..36]).try_into().expect("Incorrect amount of bytes");
-
-        let chunk_id: String = chunk_id_bytes.iter().map(|byte| *byte as char).collect();
-        let chunk_size = u32::from_le_bytes(chunk_size_bytes);
-        let audio_format = u16::from_le_bytes(audio_format_bytes);
-        let channels = u16::from_le_bytes(channels_bytes);
-        let sample_rate = u32::from_le_bytes(sample_rate_bytes);
-        let byte_rate = u32::from_le_bytes(byte_rate_bytes);
-        let block_align = u16::from_le_bytes(block_align_bytes);
-        let bits_per_sample = u16::from_le_bytes(bits_per_sample_bytes);
-
-        FmtSubChunk {
-            chunk_id,
-            chunk_size,
+    pub fn from_chunk_bytes(chunk: &Chunk, fmt_bytes: &[u8], endianness: Endianness) -> Result<Self, WavError> {
+        if fmt_bytes.len() < 16 {
+            return Err(WavError::Truncated);
+        }
+
+        let audio_format = endianness.u16(fmt_bytes, 0);
+
+        // a zero channel count leaves nothing to frame the data by, a zero rate never advances playback
+        let channels = endianness.u16(fmt_bytes, 2);
+        if channels == 0 {
+            return Err(WavError::UnsupportedChannelCount(channels));
+        }
+
+        let sample_rate = endianness.u32(fmt_bytes, 4);
+        if sample_rate == 0 {
+            return Err(WavError::InvalidSampleRate);
+        }
+
+        // bytes following cbSize, codecs like ADPCM keep their parameters here
+        let extra = if fmt_bytes.len() >= 18 {
+            let extra_size = (endianness.u16(fmt_bytes, 16) as usize).min(fmt_bytes.len() - 18);
+            fmt_bytes[18..18 + extra_size].to_vec()
+        } else {
+            Vec::new()
+        };
+
+        let extensible = if audio_format == WAVE_FORMAT_EXTENSIBLE {
+            Some(FmtExtensible::from_fmt_bytes(fmt_bytes, endianness)?)
+        } else {
+            None
+        };
+
+        Ok(FmtSubChunk {
+            chunk_id: chunk.id.clone(),
+            chunk_size: chunk.size,
             audio_format,
             channels,
             sample_rate,
-            byte_rate,
-            block_align,
-            bits_per_sample
+            byte_rate: endianness.u32(fmt_bytes, 8),
+            block_align: endianness.u16(fmt_bytes, 12),
+            bits_per_sample: endianness.u16(fmt_bytes, 14),
+            extensible,
+            extra
+        })
+    }
+
+    // resolves WAVE_FORMAT_EXTENSIBLE to the format code embedded in its SubFormat GUID
+    pub fn format_code(&self) -> u16 {
+        match &self.extensible {
+            Some(extensible) => extensible.format_code().unwrap_or(WAVE_FORMAT_EXTENSIBLE),
+            None => self.audio_format
+        }
+    }
+
+    pub fn channel_mask(&self) -> u32 {
+        match &self.extensible {
+            Some(extensible) if extensible.channel_mask != 0 => extensible.channel_mask,
+            _ => default_channel_mask(self.channels)
         }
     }
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
+pub struct FmtExtensible {
+    pub valid_bits_per_sample: u16,
+    pub channel_mask: u32,
+    pub sub_format: [u8; 16]
+}
+
+impl FmtExtensible {
+    pub fn from_fmt_bytes(fmt_bytes: &[u8], endianness: Endianness) -> Result<Self, WavError> {
+        // 16 byte basic layout, 2 byte cbSize and a 22 byte extension
+        if fmt_bytes.len() < 40 || endianness.u16(fmt_bytes, 16) < 22 {
+            return Err(WavError::Truncated);
+        }
+
+        let mut sub_format = [0u8; 16];
+        sub_format.copy_from_slice(&fmt_bytes[24..40]);
+
+        Ok(FmtExtensible {
+            valid_bits_per_sample: endianness.u16(fmt_bytes, 18),
+            channel_mask: endianness.u32(fmt_bytes, 20),
+            sub_format
+        })
+    }
+
+    pub fn format_code(&self) -> Option<u16> {
+        if self.sub_format[2..16] == SUBTYPE_GUID_SUFFIX {
+            Some(le_u16(&self.sub_format, 0))
+        } else {
+            None
+        }
+    }
+}
+
+// speaker layouts assumed for files that don't carry an explicit channel mask
+pub fn default_channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => SPEAKER_FRONT_CENTER,
+        2 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT,
+        3 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER,
+        4 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT,
+        5 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT,
+        6 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER | SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT,
+        8 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER | SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT | SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT,
+        _ => 0
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
 pub struct DataSubChunk {
     pub chunk_id: String,
-    pub chunk_size: u32,
+    pub chunk_size: u64,
+    pub offset: u64
 }
 
 impl DataSubChunk {
-    pub fn from_header_bytes(wav_header: &Vec<u8>) -> Self {
-        let chunk_id_bytes = &wav_header[36..40];
-        let chunk_size_bytes: [u8; 4] = (&wav_header[40..44]).try_into().expect("Incorrect amount of bytes");
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        DataSubChunk {
+            chunk_id: chunk.id.clone(),
+            chunk_size: chunk.size,
+            offset: chunk.offset
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FactSubChunk {
+    pub sample_length: u64
+}
 
-        let chunk_id: String = chunk_id_bytes.iter().map(|byte| *byte as char).collect();
-        let chunk_size = u32::from_le_bytes(chunk_size_bytes);
+impl FactSubChunk {
+    pub fn from_chunk_bytes(fact_bytes: &[u8], endianness: Endianness) -> Result<Self, WavError> {
+        if fact_bytes.len() < 4 {
+            return Err(WavError::Truncated);
+        }
 
-        DataSubChunk {
-            chunk_id,
-            chunk_size
+        Ok(FactSubChunk {
+            sample_length: endianness.u32(fact_bytes, 0) as u64
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big
+}
+
+impl Endianness {
+    pub fn u16(&self, bytes: &[u8], at: usize) -> u16 {
+        let value = [bytes[at], bytes[at + 1]];
+        match self {
+            Endianness::Little => u16::from_le_bytes(value),
+            Endianness::Big => u16::from_be_bytes(value)
+        }
+    }
+
+    pub fn u32(&self, bytes: &[u8], at: usize) -> u32 {
+        let value = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        match self {
+            Endianness::Little => u32::from_le_bytes(value),
+            Endianness::Big => u32::from_be_bytes(value)
         }
     }
 }
 
+fn read_chunk_body<R: Read>(reader: &mut R, chunk: &Chunk, stream_end: u64) -> Result<Vec<u8>, WavError> {
+    if chunk.offset.checked_add(chunk.size).is_none_or(|end| end > stream_end) {
+        return Err(WavError::Truncated);
+    }
+
+    let mut bytes = vec![0u8; chunk.size as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+// maps the Wave64 GUIDs back to the fourcc their RIFF counterpart uses, unknown GUIDs are kept as hex
+fn w64_chunk_id(guid: &[u8]) -> String {
+    if guid[4..16] == W64_CHUNK_GUID_SUFFIX || guid[4..16] == W64_LIST_GUID_SUFFIX {
+        return four_cc(&guid[0..4]);
+    }
+
+    guid.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn four_cc(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| *byte as char).collect()
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> u64 {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{default_channel_mask, Codec, Container, Endianness, FactSubChunk, WavError, WavHeader, SUBTYPE_GUID_SUFFIX, W64_CHUNK_GUID_SUFFIX, W64_RIFF_GUID, W64_WAVE_GUID, WAVE_FORMAT_EXTENSIBLE, WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_PCM};
+
+    // a canonical 16-byte fmt chunk body
+    fn fmt(format_code: u16, channels: u16, sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+        let mut bytes = Vec::new();
+        bytes.extend(format_code.to_le_bytes());
+        bytes.extend(channels.to_le_bytes());
+        bytes.extend(sample_rate.to_le_bytes());
+        bytes.extend((sample_rate * block_align as u32).to_le_bytes());
+        bytes.extend(block_align.to_le_bytes());
+        bytes.extend(bits_per_sample.to_le_bytes());
+        bytes
+    }
+
+    // a WAVE_FORMAT_EXTENSIBLE fmt chunk body with a KSDATAFORMAT_SUBTYPE GUID for the format code
+    fn extensible_fmt(format_code: u16, channels: u16, bits_per_sample: u16, channel_mask: u32) -> Vec<u8> {
+        let mut bytes = fmt(WAVE_FORMAT_EXTENSIBLE, channels, 48000, bits_per_sample);
+        bytes.extend(22u16.to_le_bytes());
+        bytes.extend(bits_per_sample.to_le_bytes());
+        bytes.extend(channel_mask.to_le_bytes());
+        bytes.extend(format_code.to_le_bytes());
+        bytes.extend(SUBTYPE_GUID_SUFFIX);
+        bytes
+    }
+
+    fn chunk(id: &[u8; 4], size: u32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend(size.to_le_bytes());
+        bytes.extend(body);
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn riff(chunks: &[(&[u8; 4], &[u8])]) -> Cursor<Vec<u8>> {
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk_body) in chunks {
+            body.extend(chunk(id, chunk_body.len() as u32, chunk_body));
+        }
+
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn reads_pcm_header() {
+        let mut cursor = riff(&[(b"fmt ", &fmt(WAVE_FORMAT_PCM, 2, 44100, 16)), (b"data", &[0u8; 8])]);
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!((header.fmt.channels, header.fmt.sample_rate, header.fmt.bits_per_sample), (2, 44100, 16));
+        assert_eq!((header.data.offset, header.data.chunk_size), (44, 8));
+    }
+
+    #[test]
+    fn reads_float_header_with_fact() {
+        let mut cursor = riff(&[(b"fmt ", &fmt(WAVE_FORMAT_IEEE_FLOAT, 1, 48000, 32)), (b"fact", &3u32.to_le_bytes()), (b"data", &[0u8; 12])]);
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.fmt.format_code(), WAVE_FORMAT_IEEE_FLOAT);
+        assert_eq!(header.fact.map(|fact| fact.sample_length), Some(3));
+    }
+
+    #[test]
+    fn rejects_short_fact() {
+        assert!(matches!(FactSubChunk::from_chunk_bytes(&[0x10, 0x00], Endianness::Little), Err(WavError::Truncated)));
+    }
+
+    #[test]
+    fn reads_extensible_fmt() {
+        let mut cursor = riff(&[(b"fmt ", &extensible_fmt(WAVE_FORMAT_IEEE_FLOAT, 6, 32, 0x60F)), (b"data", &[0u8; 24])]);
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.fmt.audio_format, WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(header.fmt.format_code(), WAVE_FORMAT_IEEE_FLOAT);
+        assert_eq!(header.fmt.channel_mask(), 0x60F);
+    }
+
+    #[test]
+    fn falls_back_to_default_channel_mask() {
+        let mut cursor = riff(&[(b"fmt ", &extensible_fmt(WAVE_FORMAT_PCM, 6, 24, 0)), (b"data", &[0u8; 18])]);
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.fmt.channel_mask(), default_channel_mask(6));
+        assert_eq!(default_channel_mask(6), 0x3F);
+    }
+
+    #[test]
+    fn rejects_short_extensible_fmt() {
+        let mut fmt_bytes = extensible_fmt(WAVE_FORMAT_PCM, 2, 16, 0x3);
+        fmt_bytes.truncate(32);
+        let mut cursor = riff(&[(b"fmt ", &fmt_bytes), (b"data", &[0u8; 4])]);
+
+        assert!(matches!(WavHeader::from_reader(&mut cursor), Err(WavError::Truncated)));
+    }
+
+    // ds64 body with the RIFF, data and sample count sizes and no table
+    fn ds64(riff_size: u64, data_size: u64, sample_count: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(riff_size.to_le_bytes());
+        bytes.extend(data_size.to_le_bytes());
+        bytes.extend(sample_count.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes
+    }
+
+    // Wave64 chunks are named by the GUID that starts with their fourcc, sizes count the 24-byte header
+    fn w64_chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend(W64_CHUNK_GUID_SUFFIX);
+        bytes.extend((body.len() as u64 + 24).to_le_bytes());
+        bytes.extend(body);
+        bytes.resize(bytes.len().next_multiple_of(8), 0);
+        bytes
+    }
+
+    #[test]
+    fn reads_rf64_sizes_from_ds64() {
+        let mut body = b"WAVE".to_vec();
+        body.extend(chunk(b"ds64", 28, &ds64(0, 16, 4)));
+        body.extend(chunk(b"fmt ", 16, &fmt(WAVE_FORMAT_PCM, 2, 44100, 16)));
+        body.extend(chunk(b"data", u32::MAX, &[0u8; 16]));
+
+        let mut bytes = b"BW64".to_vec();
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend(&body);
+        let riff_size = bytes.len() as u64 - 8;
+        bytes[20..28].copy_from_slice(&riff_size.to_le_bytes());
+
+        let header = WavHeader::from_reader(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(header.riff.container, Container::Rf64);
+        assert_eq!(header.ds64.as_ref().map(|ds64| (ds64.riff_size, ds64.sample_count)), Some((riff_size, 4)));
+        assert_eq!(header.data.chunk_size, 16);
+    }
+
+    #[test]
+    fn reads_wave64() {
+        let mut body = w64_chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 1, 96000, 24));
+        body.extend(w64_chunk(b"data", &[0u8; 9]));
+
+        let mut bytes = W64_RIFF_GUID.to_vec();
+        bytes.extend((body.len() as u64 + 40).to_le_bytes());
+        bytes.extend(W64_WAVE_GUID);
+        bytes.extend(body);
+
+        let header = WavHeader::from_reader(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(header.riff.container, Container::Wave64);
+        assert_eq!((header.fmt.channels, header.fmt.sample_rate, header.fmt.bits_per_sample), (1, 96000, 24));
+        // the header and the 40-byte fmt chunk come first, then the data chunk's own 24-byte header
+        assert_eq!((header.data.offset, header.data.chunk_size), (104, 9));
+    }
+
+    #[test]
+    fn rejects_short_ds64() {
+        let mut cursor = Cursor::new([b"RF64".as_slice(), &u32::MAX.to_le_bytes(), b"WAVE", &chunk(b"ds64", 20, &[0u8; 20])].concat());
+
+        assert!(matches!(WavHeader::from_reader(&mut cursor), Err(WavError::Truncated)));
+    }
+
+    #[test]
+    fn rejects_wave64_chunks_past_the_end() {
+        let mut fmt_chunk = w64_chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 1, 96000, 24));
+        fmt_chunk[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let mut bytes = W64_RIFF_GUID.to_vec();
+        bytes.extend((fmt_chunk.len() as u64 + 40).to_le_bytes());
+        bytes.extend(W64_WAVE_GUID);
+        bytes.extend(fmt_chunk);
+
+        assert!(matches!(WavHeader::from_reader(&mut Cursor::new(bytes)), Err(WavError::Truncated)));
+    }
+
+    #[test]
+    fn reads_big_endian_rifx() {
+        let mut fmt_bytes = Vec::new();
+        fmt_bytes.extend(WAVE_FORMAT_PCM.to_be_bytes());
+        fmt_bytes.extend(2u16.to_be_bytes());
+        fmt_bytes.extend(22050u32.to_be_bytes());
+        fmt_bytes.extend(88200u32.to_be_bytes());
+        fmt_bytes.extend(4u16.to_be_bytes());
+        fmt_bytes.extend(16u16.to_be_bytes());
+
+        let mut body = b"WAVEfmt ".to_vec();
+        body.extend(16u32.to_be_bytes());
+        body.extend(fmt_bytes);
+        body.extend(b"data");
+        body.extend(4u32.to_be_bytes());
+        body.extend([0x40, 0x00, 0xC0, 0x00]);
+
+        let mut bytes = b"RIFX".to_vec();
+        bytes.extend((body.len() as u32).to_be_bytes());
+        bytes.extend(body);
+
+        let header = WavHeader::from_reader(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(header.riff.container, Container::Rifx);
+        assert_eq!((header.fmt.channels, header.fmt.sample_rate, header.fmt.bits_per_sample), (2, 22050, 16));
+        assert_eq!(header.data.chunk_size, 4);
+
+        let codec = Codec::from_fmt(&header.fmt, Endianness::Big).unwrap();
+        let mut samples = Vec::new();
+        codec.decode_block(&[0x40, 0x00, 0xC0, 0x00], &mut samples);
+        assert_eq!(samples, [0.5, -0.5]);
+    }
+
+    #[test]
+    fn rejects_unknown_containers() {
+        let mut cursor = Cursor::new(b"RIFZ\x04\0\0\0WAVE".to_vec());
+        assert!(matches!(WavHeader::from_reader(&mut cursor), Err(WavError::UnknownContainer(id)) if id == "RIFZ"));
+
+        let mut cursor = Cursor::new(b"RIFF\x04\0\0\0AVI ".to_vec());
+        assert!(matches!(WavHeader::from_reader(&mut cursor), Err(WavError::InvalidMagic)));
+    }
+
+    #[test]
+    fn rejects_zero_channels() {
+        let mut cursor = riff(&[(b"fmt ", &fmt(WAVE_FORMAT_PCM, 0, 44100, 16)), (b"data", &[0u8; 8])]);
+
+        assert!(matches!(WavHeader::from_reader(&mut cursor), Err(WavError::UnsupportedChannelCount(0))));
+    }
+
+    #[test]
+    fn rejects_zero_sample_rate() {
+        let mut cursor = riff(&[(b"fmt ", &fmt(WAVE_FORMAT_PCM, 2, 0, 16)), (b"data", &[0u8; 8])]);
+
+        assert!(matches!(WavHeader::from_reader(&mut cursor), Err(WavError::InvalidSampleRate)));
+    }
+}
@@ -0,0 +1,550 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::bwf::BextChunk;
+use crate::markers::Marker;
+use crate::metadata::Metadata;
+use crate::sample_format::SampleFormat;
+use crate::wav::{default_channel_mask, WavError, SUBTYPE_GUID_SUFFIX, WAVE_FORMAT_EXTENSIBLE, WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_PCM};
+
+// a JUNK chunk right after the RIFF header reserves the room a ds64 chunk needs, should the file outgrow RIFF
+const DS64_SIZE: u64 = 28;
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub struct WavSpec {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+    pub channel_mask: u32
+}
+
+#[allow(dead_code)]
+impl WavSpec {
+    pub fn new(channels: u16, sample_rate: u32, sample_format: SampleFormat) -> Self {
+        WavSpec {
+            channels,
+            sample_rate,
+            sample_format,
+            channel_mask: default_channel_mask(channels)
+        }
+    }
+}
+
+// Writes PCM and float WAVE files. Chunks added before the first samples go in front of the data chunk,
+// later ones and the cue points follow it. Sizes are patched in on finalize, which also turns the file
+// into RF64 once it no longer fits the 32-bit RIFF sizes.
+#[allow(dead_code)]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    bytes: Vec<u8>,
+    // absolute offset of the fact chunk body and of the data chunk header, once written
+    fact_offset: Option<u64>,
+    data_offset: Option<u64>,
+    data_size: u64,
+    trailing_chunks: Vec<u8>,
+    markers: Vec<Marker>,
+    // files with a larger RIFF size are written as RF64
+    riff_size_limit: u64,
+    finalized: bool
+}
+
+#[allow(dead_code)]
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, spec: WavSpec) -> Result<Self, WavError> {
+        WavWriter::new(BufWriter::new(File::create(path)?), spec)
+    }
+}
+
+#[allow(dead_code)]
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, spec: WavSpec) -> Result<Self, WavError> {
+        let format_code = match spec.sample_format {
+            SampleFormat::U8 | SampleFormat::I16 | SampleFormat::I24 | SampleFormat::I32 => WAVE_FORMAT_PCM,
+            SampleFormat::F32 | SampleFormat::F64 => WAVE_FORMAT_IEEE_FLOAT,
+            sample_format => return Err(WavError::UnsupportedSampleFormat(sample_format))
+        };
+        if spec.channels == 0 {
+            return Err(WavError::UnsupportedChannelCount(spec.channels));
+        }
+        if spec.sample_rate == 0 {
+            return Err(WavError::InvalidSampleRate);
+        }
+        let fmt = fmt_bytes(&spec, format_code)?;
+
+        writer.write_all(b"RIFF\0\0\0\0WAVE")?;
+        writer.write_all(&chunk(b"JUNK", &[0u8; DS64_SIZE as usize]))?;
+        writer.write_all(&chunk(b"fmt ", &fmt))?;
+
+        // float data is not PCM, so it needs a fact chunk with the frame count
+        let fact_offset = if format_code == WAVE_FORMAT_PCM {
+            None
+        } else {
+            writer.write_all(&chunk(b"fact", &[0u8; 4]))?;
+            Some(writer.stream_position()? - 4)
+        };
+
+        Ok(WavWriter {
+            writer,
+            spec,
+            bytes: Vec::new(),
+            fact_offset,
+            data_offset: None,
+            data_size: 0,
+            trailing_chunks: Vec::new(),
+            markers: Vec::new(),
+            riff_size_limit: u32::MAX as u64,
+            finalized: false
+        })
+    }
+
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.data_size / (self.spec.sample_format.bytes_per_sample() * self.spec.channels as usize) as u64
+    }
+
+    pub fn write_info(&mut self, metadata: &Metadata) -> Result<(), WavError> {
+        self.write_chunk(b"LIST", &info_list_bytes(metadata))
+    }
+
+    pub fn write_bext(&mut self, bext: &BextChunk) -> Result<(), WavError> {
+        self.write_chunk(b"bext", &bext_bytes(bext))
+    }
+
+    // lowers the size at which the file turns into RF64, it can't go past what the 32-bit RIFF fields hold
+    pub fn set_riff_size_limit(&mut self, riff_size_limit: u64) {
+        self.riff_size_limit = riff_size_limit.min(u32::MAX as u64);
+    }
+
+    // cue points and their labels are written on finalize, after the data chunk.
+    // cue positions are 32-bit even in RF64, markers past that can't be written
+    pub fn add_marker(&mut self, marker: Marker) -> Result<(), WavError> {
+        if marker.position > u32::MAX as u64 {
+            return Err(WavError::MarkerOutOfRange(marker.position));
+        }
+        self.markers.push(marker);
+
+        Ok(())
+    }
+
+    // interleaved samples in the -1.0..1.0 range, integer formats clip anything outside of it
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<(), WavError> {
+        if self.data_offset.is_none() {
+            self.data_offset = Some(self.writer.stream_position()?);
+            self.writer.write_all(b"data\0\0\0\0")?;
+        }
+
+        self.bytes.clear();
+        for sample in samples {
+            encode_sample(self.spec.sample_format, *sample, &mut self.bytes);
+        }
+
+        self.writer.write_all(&self.bytes)?;
+        self.data_size += self.bytes.len() as u64;
+
+        Ok(())
+    }
+
+    // writes the trailing chunks and patches the sizes in the headers
+    pub fn finalize(mut self) -> Result<(), WavError> {
+        self.finish()
+    }
+
+    fn write_chunk(&mut self, id: &[u8; 4], body: &[u8]) -> Result<(), WavError> {
+        if self.data_offset.is_some() {
+            self.trailing_chunks.extend(chunk(id, body));
+        } else {
+            self.writer.write_all(&chunk(id, body))?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WavError> {
+        if self.finalized {
+            return Ok(());
+        }
+        self.finalized = true;
+
+        if self.data_offset.is_none() {
+            self.write_samples(&[])?;
+        }
+        let data_offset = self.data_offset.unwrap_or_default();
+
+        if self.data_size & 1 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+
+        if !self.markers.is_empty() {
+            self.markers.sort_by_key(|marker| marker.position);
+            self.trailing_chunks.extend(chunk(b"cue ", &cue_bytes(&self.markers)));
+            if self.markers.iter().any(|marker| marker.name.is_some()) {
+                self.trailing_chunks.extend(chunk(b"LIST", &adtl_list_bytes(&self.markers)));
+            }
+        }
+        self.writer.write_all(&self.trailing_chunks)?;
+
+        let riff_size = self.writer.stream_position()? - 8;
+        let frames = self.frames_written();
+
+        if riff_size > self.riff_size_limit {
+            // RF64 keeps the 32-bit fields at their maximum and moves the real sizes into ds64
+            let mut ds64 = Vec::with_capacity(DS64_SIZE as usize);
+            ds64.extend(riff_size.to_le_bytes());
+            ds64.extend(self.data_size.to_le_bytes());
+            ds64.extend(frames.to_le_bytes());
+            ds64.extend(0u32.to_le_bytes());
+
+            self.patch(0, b"RF64")?;
+            self.patch(4, &u32::MAX.to_le_bytes())?;
+            self.patch(12, &chunk(b"ds64", &ds64))?;
+            self.patch(data_offset + 4, &u32::MAX.to_le_bytes())?;
+            if let Some(fact_offset) = self.fact_offset {
+                self.patch(fact_offset, &u32::MAX.to_le_bytes())?;
+            }
+        } else {
+            self.patch(4, &(riff_size as u32).to_le_bytes())?;
+            self.patch(data_offset + 4, &(self.data_size as u32).to_le_bytes())?;
+            if let Some(fact_offset) = self.fact_offset {
+                self.patch(fact_offset, &(frames as u32).to_le_bytes())?;
+            }
+        }
+
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    fn patch(&mut self, offset: u64, bytes: &[u8]) -> Result<(), WavError> {
+        self.writer.seek(SeekFrom::Start(offset))?;
+        self.writer.write_all(bytes)?;
+
+        Ok(())
+    }
+}
+
+// a writer that's dropped without finalize still leaves a readable file behind
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(body.len() + 9);
+    bytes.extend_from_slice(id);
+    bytes.extend((body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(body);
+
+    // chunks are word aligned
+    if body.len() & 1 == 1 {
+        bytes.push(0);
+    }
+
+    bytes
+}
+
+// layouts beyond plain stereo need WAVE_FORMAT_EXTENSIBLE to carry their channel mask.
+// the block align and byte rate fields are 16 and 32-bit, specs that outgrow them can't be written
+fn fmt_bytes(spec: &WavSpec, format_code: u16) -> Result<Vec<u8>, WavError> {
+    let bytes_per_sample = spec.sample_format.bytes_per_sample() as u16;
+    let block_align = bytes_per_sample.checked_mul(spec.channels).ok_or(WavError::UnsupportedChannelCount(spec.channels))?;
+    let byte_rate = spec.sample_rate.checked_mul(block_align as u32).ok_or(WavError::InvalidSampleRate)?;
+    let bits_per_sample = bytes_per_sample * 8;
+    let extensible = spec.channels > 2 || spec.channel_mask != default_channel_mask(spec.channels);
+
+    let mut bytes = Vec::with_capacity(40);
+    bytes.extend((if extensible { WAVE_FORMAT_EXTENSIBLE } else { format_code }).to_le_bytes());
+    bytes.extend(spec.channels.to_le_bytes());
+    bytes.extend(spec.sample_rate.to_le_bytes());
+    bytes.extend(byte_rate.to_le_bytes());
+    bytes.extend(block_align.to_le_bytes());
+    bytes.extend(bits_per_sample.to_le_bytes());
+
+    if extensible {
+        bytes.extend(22u16.to_le_bytes());
+        bytes.extend(bits_per_sample.to_le_bytes());
+        bytes.extend(spec.channel_mask.to_le_bytes());
+        bytes.extend(format_code.to_le_bytes());
+        bytes.extend(SUBTYPE_GUID_SUFFIX);
+    } else if format_code != WAVE_FORMAT_PCM {
+        // only PCM may leave out cbSize
+        bytes.extend(0u16.to_le_bytes());
+    }
+
+    Ok(bytes)
+}
+
+fn encode_sample(sample_format: SampleFormat, sample: f32, bytes: &mut Vec<u8>) {
+    let integer = |scale: f64| (sample as f64 * scale).round().clamp(-scale, scale - 1.0) as i32;
+
+    match sample_format {
+        SampleFormat::U8 => bytes.push((integer(128.0) + 128) as u8),
+        SampleFormat::I16 => bytes.extend((integer(32768.0) as i16).to_le_bytes()),
+        SampleFormat::I24 => bytes.extend(&integer(8388608.0).to_le_bytes()[0..3]),
+        SampleFormat::I32 => bytes.extend(integer(2147483648.0).to_le_bytes()),
+        SampleFormat::F32 => bytes.extend(sample.to_le_bytes()),
+        SampleFormat::F64 => bytes.extend((sample as f64).to_le_bytes()),
+        SampleFormat::I8 | SampleFormat::ALaw | SampleFormat::MuLaw => unreachable!("rejected by WavWriter::new")
+    }
+}
+
+// INFO list with the fields Metadata::from_info_list reads back
+fn info_list_bytes(metadata: &Metadata) -> Vec<u8> {
+    let fields = [
+        (b"INAM", &metadata.title),
+        (b"IART", &metadata.artist),
+        (b"IPRD", &metadata.album),
+        (b"ICRD", &metadata.date),
+        (b"IGNR", &metadata.genre),
+        (b"ITRK", &metadata.track),
+        (b"ICMT", &metadata.comment)
+    ];
+
+    let mut bytes = b"INFO".to_vec();
+    for (id, value) in fields {
+        if let Some(value) = value {
+            bytes.extend(chunk(id, &zero_terminated(value)));
+        }
+    }
+
+    bytes
+}
+
+// the EBU Tech 3285 layout, loudness values need version 2
+fn bext_bytes(bext: &BextChunk) -> Vec<u8> {
+    let loudness = [
+        bext.loudness_value,
+        bext.loudness_range,
+        bext.max_true_peak_level,
+        bext.max_momentary_loudness,
+        bext.max_short_term_loudness
+    ];
+    let version = if loudness.iter().any(Option::is_some) {
+        bext.version.max(2)
+    } else {
+        bext.version
+    };
+
+    let mut bytes = Vec::with_capacity(602 + bext.coding_history.len());
+    bytes.extend(fixed_text(&bext.description, 256));
+    bytes.extend(fixed_text(&bext.originator, 32));
+    bytes.extend(fixed_text(&bext.originator_reference, 32));
+    bytes.extend(fixed_text(&bext.origination_date, 10));
+    bytes.extend(fixed_text(&bext.origination_time, 8));
+    bytes.extend(bext.time_reference.to_le_bytes());
+    bytes.extend(version.to_le_bytes());
+    // UMID
+    bytes.extend([0u8; 64]);
+    for value in loudness {
+        let value = value.map_or(0x7FFF, |value| (value * 100.0).round() as i16);
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.resize(602, 0);
+    bytes.extend(bext.coding_history.as_bytes());
+
+    bytes
+}
+
+// cue points in sample frames from the start of the data chunk
+fn cue_bytes(markers: &[Marker]) -> Vec<u8> {
+    let mut bytes = (markers.len() as u32).to_le_bytes().to_vec();
+    for marker in markers {
+        let position = marker.position as u32;
+        bytes.extend(marker.id.to_le_bytes());
+        bytes.extend(position.to_le_bytes());
+        bytes.extend(b"data");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(position.to_le_bytes());
+    }
+
+    bytes
+}
+
+// labl sub chunks naming the cue points
+fn adtl_list_bytes(markers: &[Marker]) -> Vec<u8> {
+    let mut bytes = b"adtl".to_vec();
+    for marker in markers {
+        if let Some(name) = &marker.name {
+            let mut label = marker.id.to_le_bytes().to_vec();
+            label.extend(zero_terminated(name));
+            bytes.extend(chunk(b"labl", &label));
+        }
+    }
+
+    bytes
+}
+
+fn zero_terminated(text: &str) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+
+    bytes
+}
+
+fn fixed_text(text: &str, length: usize) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.resize(length, 0);
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{WavSpec, WavWriter};
+    use crate::markers::Marker;
+    use crate::metadata::Metadata;
+    use crate::sample_format::SampleFormat;
+    use crate::wav::{Container, WavError, WavHeader, WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_PCM};
+
+    // a ramp through the whole range, every value is exact in 16-bit and float
+    fn ramp(samples: usize) -> Vec<f32> {
+        (0..samples).map(|index| (index % 512) as f32 / 256.0 - 1.0).collect()
+    }
+
+    fn write<F: FnOnce(&mut WavWriter<&mut Cursor<Vec<u8>>>)>(spec: WavSpec, callback: F) -> Cursor<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut cursor, spec).unwrap();
+        callback(&mut writer);
+        writer.finalize().unwrap();
+
+        cursor
+    }
+
+    fn read_samples(cursor: &Cursor<Vec<u8>>, header: &WavHeader) -> Vec<f32> {
+        let sample_format = SampleFormat::from_fmt(&header.fmt).unwrap();
+        let start = header.data.offset as usize;
+        let data = &cursor.get_ref()[start..start + header.data.chunk_size as usize];
+
+        data.chunks_exact(sample_format.bytes_per_sample()).map(|sample| sample_format.decode(sample)).collect()
+    }
+
+    #[test]
+    fn pcm_16_bit_round_trip() {
+        let samples = ramp(2000);
+        let mut cursor = write(WavSpec::new(2, 44100, SampleFormat::I16), |writer| {
+            writer.write_samples(&samples).unwrap();
+        });
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.riff.container, Container::Riff);
+        assert_eq!(header.fmt.audio_format, WAVE_FORMAT_PCM);
+        assert_eq!(header.fmt.chunk_size, 16);
+        assert_eq!((header.fmt.channels, header.fmt.sample_rate, header.fmt.bits_per_sample), (2, 44100, 16));
+        assert_eq!(header.data.chunk_size, 4000);
+        assert!(header.fact.is_none());
+        assert_eq!(read_samples(&cursor, &header), samples);
+    }
+
+    #[test]
+    fn float_has_cb_size_and_fact() {
+        let samples = ramp(3000);
+        let mut cursor = write(WavSpec::new(2, 48000, SampleFormat::F32), |writer| {
+            writer.write_samples(&samples[..1000]).unwrap();
+            writer.write_samples(&samples[1000..]).unwrap();
+        });
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.fmt.audio_format, WAVE_FORMAT_IEEE_FLOAT);
+        assert_eq!(header.fmt.chunk_size, 18);
+        assert_eq!(header.fact.as_ref().map(|fact| fact.sample_length), Some(1500));
+        assert_eq!(read_samples(&cursor, &header), samples);
+    }
+
+    #[test]
+    fn info_cue_and_labels() {
+        let metadata = Metadata {
+            title: Some(String::from("Title")),
+            artist: Some(String::from("Artist")),
+            ..Metadata::default()
+        };
+        let mut cursor = write(WavSpec::new(1, 22050, SampleFormat::I16), |writer| {
+            writer.write_info(&metadata).unwrap();
+            writer.add_marker(Marker { id: 2, position: 700, name: None }).unwrap();
+            writer.add_marker(Marker { id: 1, position: 100, name: Some(String::from("Verse")) }).unwrap();
+            writer.write_samples(&ramp(1000)).unwrap();
+        });
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        let info = header.info.unwrap();
+        assert_eq!(info.title.as_deref(), Some("Title"));
+        assert_eq!(info.artist.as_deref(), Some("Artist"));
+        // the LIST chunk was added before the samples, so it's in front of the data
+        assert!(header.chunks.iter().position(|chunk| chunk.id == "LIST") < header.chunks.iter().position(|chunk| chunk.id == "data"));
+
+        let positions: Vec<u64> = header.cue_points.iter().map(|cue_point| cue_point.position).collect();
+        assert_eq!(positions, [100, 700]);
+        assert_eq!(header.labels.get(&1).map(String::as_str), Some("Verse"));
+        assert!(!header.labels.contains_key(&2));
+    }
+
+    #[test]
+    fn odd_data_is_padded_before_trailing_chunks() {
+        let samples = [-1.0, 0.0, 0.5];
+        let metadata = Metadata {
+            title: Some(String::from("Odd")),
+            ..Metadata::default()
+        };
+        let mut cursor = write(WavSpec::new(1, 8000, SampleFormat::U8), |writer| {
+            writer.write_samples(&samples).unwrap();
+            writer.write_info(&metadata).unwrap();
+        });
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.data.chunk_size, 3);
+        assert_eq!(cursor.get_ref()[(header.data.offset + 3) as usize], 0);
+        assert_eq!(header.info.as_ref().and_then(|info| info.title.as_deref()), Some("Odd"));
+        assert_eq!(read_samples(&cursor, &header), samples);
+    }
+
+    #[test]
+    fn rejects_specs_that_overflow_fmt() {
+        let new = |spec| WavWriter::new(Cursor::new(Vec::new()), spec).err();
+
+        assert!(matches!(new(WavSpec::new(8, u32::MAX / 4, SampleFormat::F64)), Some(WavError::InvalidSampleRate)));
+        assert!(matches!(new(WavSpec::new(u16::MAX, 44100, SampleFormat::I32)), Some(WavError::UnsupportedChannelCount(u16::MAX))));
+        assert!(matches!(new(WavSpec::new(2, 0, SampleFormat::I16)), Some(WavError::InvalidSampleRate)));
+    }
+
+    #[test]
+    fn rejects_markers_past_32_bits() {
+        write(WavSpec::new(1, 44100, SampleFormat::I16), |writer| {
+            let marker = Marker { id: 1, position: u32::MAX as u64 + 1, name: None };
+            assert!(matches!(writer.add_marker(marker), Err(WavError::MarkerOutOfRange(_))));
+        });
+    }
+
+    #[test]
+    fn promotes_to_rf64() {
+        // a limit small enough to reach RF64 with a few thousand samples
+        let riff_size_limit = 16384;
+        let samples = ramp(riff_size_limit as usize / 2);
+        let mut cursor = write(WavSpec::new(2, 44100, SampleFormat::F32), |writer| {
+            writer.set_riff_size_limit(riff_size_limit);
+            writer.add_marker(Marker { id: 1, position: 10, name: Some(String::from("Start")) }).unwrap();
+            writer.write_samples(&samples).unwrap();
+        });
+        let header = WavHeader::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(header.riff.container, Container::Rf64);
+        // the ds64 chunk took the JUNK chunk's place
+        assert_eq!(header.chunks[0].id, "ds64");
+        let ds64 = header.ds64.as_ref().unwrap();
+        assert_eq!(ds64.riff_size, cursor.get_ref().len() as u64 - 8);
+        assert_eq!(ds64.data_size, samples.len() as u64 * 4);
+        assert_eq!(ds64.sample_count, samples.len() as u64 / 2);
+        assert_eq!(header.data.chunk_size, ds64.data_size);
+        assert_eq!(header.fact.as_ref().map(|fact| fact.sample_length), Some(ds64.sample_count));
+        assert_eq!(header.labels.get(&1).map(String::as_str), Some("Start"));
+        assert_eq!(read_samples(&cursor, &header), samples);
+    }
+}